@@ -0,0 +1,195 @@
+use crate::keycode::Keycode;
+
+/// Something that can inject keypresses into the focused application.
+pub trait Keyboard {
+    fn key_down(&mut self, key: Keycode);
+    fn key_up(&mut self, key: Keycode);
+    fn key_click(&mut self, key: Keycode);
+    /// Types out `text` as-is. `text` should not contain newlines; those are
+    /// handled by the caller, as their behaviour is configurable.
+    fn type_text(&mut self, text: &str);
+}
+
+/// Something that can read and write the text contents of a clipboard.
+pub trait Clipboard {
+    fn get_text(&mut self) -> anyhow::Result<String>;
+    fn set_text(&mut self, text: &str) -> anyhow::Result<()>;
+}
+
+/// The contents of a clipboard at some point in time, so that they can be put
+/// back after alpa has used the clipboard for its own purposes.
+pub struct SavedClipboard(Option<String>);
+impl SavedClipboard {
+    pub fn save(clipboard: &mut dyn Clipboard) -> Self {
+        Self(clipboard.get_text().ok())
+    }
+
+    pub fn restore(self, clipboard: &mut dyn Clipboard) -> anyhow::Result<()> {
+        match self.0 {
+            Some(text) => clipboard.set_text(&text),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct EnigoKeyboard(enigo::Enigo);
+impl Default for EnigoKeyboard {
+    fn default() -> Self {
+        Self(enigo::Enigo::new())
+    }
+}
+impl EnigoKeyboard {
+    fn with_key(&mut self, key: Keycode, f: impl FnOnce(&mut enigo::Enigo, enigo::Key)) {
+        match key.to_enigo() {
+            Some(key) => f(&mut self.0, key),
+            None => eprintln!("Unable to send {key:?}: not supported by enigo"),
+        }
+    }
+}
+impl Keyboard for EnigoKeyboard {
+    fn key_down(&mut self, key: Keycode) {
+        use enigo::KeyboardControllable;
+        self.with_key(key, |e, k| e.key_down(k));
+    }
+
+    fn key_up(&mut self, key: Keycode) {
+        use enigo::KeyboardControllable;
+        self.with_key(key, |e, k| e.key_up(k));
+    }
+
+    fn key_click(&mut self, key: Keycode) {
+        use enigo::KeyboardControllable;
+        self.with_key(key, |e, k| e.key_click(k));
+    }
+
+    fn type_text(&mut self, text: &str) {
+        use enigo::KeyboardControllable;
+        self.0.key_sequence(text);
+    }
+}
+
+impl Clipboard for arboard::Clipboard {
+    fn get_text(&mut self) -> anyhow::Result<String> {
+        match retry_if_occupied(|| arboard::Clipboard::get_text(self)) {
+            Err(arboard::Error::ContentNotAvailable) => {
+                anyhow::bail!("the clipboard doesn't contain any text")
+            }
+            result => Ok(result?),
+        }
+    }
+
+    fn set_text(&mut self, text: &str) -> anyhow::Result<()> {
+        Ok(retry_if_occupied(|| {
+            arboard::Clipboard::set_text(self, text)
+        })?)
+    }
+}
+
+/// Calls `f` again if the clipboard is briefly in use by another application.
+fn retry_if_occupied<T>(
+    mut f: impl FnMut() -> Result<T, arboard::Error>,
+) -> Result<T, arboard::Error> {
+    const ATTEMPTS: usize = 5;
+    for _ in 1..ATTEMPTS {
+        match f() {
+            Err(arboard::Error::ClipboardOccupied) => {
+                std::thread::sleep(std::time::Duration::from_millis(20))
+            }
+            result => return result,
+        }
+    }
+    f()
+}
+
+/// Stand-ins for the keyboard and clipboard, so that what alpa would have sent
+/// to the focused application can be checked in tests.
+#[cfg(test)]
+pub mod fakes {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Clipboard, Keyboard};
+    use crate::keycode::Keycode;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum KeyEvent {
+        Down(Keycode),
+        Up(Keycode),
+        Click(Keycode),
+        Text(String),
+    }
+
+    /// A keyboard that records the keypresses it would have sent.
+    ///
+    /// Clones share the same record, so a clone can be handed to the code
+    /// under test while the original is kept around to inspect what was sent.
+    #[derive(Clone, Default)]
+    pub struct RecordingKeyboard {
+        events: Arc<Mutex<Vec<KeyEvent>>>,
+    }
+    impl RecordingKeyboard {
+        /// Returns all events recorded since the last call.
+        pub fn take_events(&self) -> Vec<KeyEvent> {
+            std::mem::take(&mut *self.events.lock().unwrap())
+        }
+
+        fn record(&mut self, event: KeyEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+    impl Keyboard for RecordingKeyboard {
+        fn key_down(&mut self, key: Keycode) {
+            self.record(KeyEvent::Down(key));
+        }
+
+        fn key_up(&mut self, key: Keycode) {
+            self.record(KeyEvent::Up(key));
+        }
+
+        fn key_click(&mut self, key: Keycode) {
+            self.record(KeyEvent::Click(key));
+        }
+
+        fn type_text(&mut self, text: &str) {
+            self.record(KeyEvent::Text(text.to_string()));
+        }
+    }
+
+    /// A clipboard that only lives in memory. Clones share the same contents.
+    #[derive(Clone, Default)]
+    pub struct MemoryClipboard {
+        text: Arc<Mutex<Option<String>>>,
+        /// How many more reads succeed, if they're to start failing.
+        reads_left: Arc<Mutex<Option<usize>>>,
+    }
+    impl MemoryClipboard {
+        pub fn with_text(text: &str) -> Self {
+            let clipboard = Self::default();
+            *clipboard.text.lock().unwrap() = Some(text.to_string());
+            clipboard
+        }
+
+        pub fn text(&self) -> Option<String> {
+            self.text.lock().unwrap().clone()
+        }
+
+        /// Makes every read fail once `reads` more have succeeded.
+        pub fn fail_reads_after(&self, reads: usize) {
+            *self.reads_left.lock().unwrap() = Some(reads);
+        }
+    }
+    impl Clipboard for MemoryClipboard {
+        fn get_text(&mut self) -> anyhow::Result<String> {
+            if let Some(reads_left) = &mut *self.reads_left.lock().unwrap() {
+                anyhow::ensure!(*reads_left > 0, "the clipboard couldn't be read");
+                *reads_left -= 1;
+            }
+            self.text()
+                .ok_or_else(|| anyhow::anyhow!("clipboard is empty"))
+        }
+
+        fn set_text(&mut self, text: &str) -> anyhow::Result<()> {
+            *self.text.lock().unwrap() = Some(text.to_string());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::Keyboard, command::ClipboardLoad, keycode::Keycode};
+
+/// The built-in scripts for this platform, which the config's scripts replace.
+#[cfg(target_os = "macos")]
+const PLATFORM_DEFAULTS: &str = include_str!("capture/macos.toml");
+#[cfg(not(target_os = "macos"))]
+const PLATFORM_DEFAULTS: &str = include_str!("capture/default.toml");
+
+/// Keys pressed one after another to copy text from the focused application.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyScript {
+    /// How long to wait after each key goes down or up, in milliseconds.
+    /// Some applications miss keys that arrive too quickly.
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    pub steps: Vec<KeyStep>,
+}
+
+fn default_delay_ms() -> u64 {
+    5
+}
+
+/// A key in a [`KeyScript`], pressed with some modifiers held down.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyStep {
+    /// Pressed in order before `key`, and released in reverse order after it.
+    #[serde(default)]
+    pub modifiers: Vec<Keycode>,
+    pub key: Keycode,
+    /// How long to wait after each key in this step, instead of the script's `delay_ms`.
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    /// Press this once for each line of `lines-above`, rather than once.
+    #[serde(default)]
+    pub per_line: bool,
+}
+
+impl KeyScript {
+    /// Sends the script to `keyboard`, with `lines` being the number of times
+    /// to repeat the steps that are pressed once per line.
+    pub fn run(&self, keyboard: &mut dyn Keyboard, lines: usize) {
+        for step in &self.steps {
+            let delay = Duration::from_millis(step.delay_ms.unwrap_or(self.delay_ms));
+            let pause = || {
+                if !delay.is_zero() {
+                    std::thread::sleep(delay);
+                }
+            };
+            for _ in 0..if step.per_line { lines } else { 1 } {
+                for &modifier in &step.modifiers {
+                    keyboard.key_down(modifier);
+                    pause();
+                }
+                keyboard.key_click(step.key);
+                pause();
+                for &modifier in step.modifiers.iter().rev() {
+                    keyboard.key_up(modifier);
+                    pause();
+                }
+            }
+        }
+    }
+}
+
+/// The scripts used to copy the text for each [`ClipboardLoad`]. Any that
+/// aren't set in the config are filled in with the platform's defaults by
+/// [`CaptureKeys::add_defaults`] when it's loaded.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CaptureKeys {
+    #[serde(default)]
+    pub line: Option<KeyScript>,
+    #[serde(default)]
+    pub selection: Option<KeyScript>,
+    #[serde(default)]
+    pub paragraph: Option<KeyScript>,
+    #[serde(default)]
+    pub document: Option<KeyScript>,
+    #[serde(default)]
+    pub word: Option<KeyScript>,
+    #[serde(default)]
+    pub lines_above: Option<KeyScript>,
+}
+impl CaptureKeys {
+    /// Uses this platform's default script for each mode that doesn't have one.
+    /// Fails if the defaults can't be read, or don't cover every mode.
+    pub fn add_defaults(&mut self) -> anyhow::Result<()> {
+        let defaults: CaptureKeys =
+            toml::from_str(PLATFORM_DEFAULTS).context("the built-in capture keys are invalid")?;
+        for (name, script, default) in [
+            ("line", &mut self.line, defaults.line),
+            ("selection", &mut self.selection, defaults.selection),
+            ("paragraph", &mut self.paragraph, defaults.paragraph),
+            ("document", &mut self.document, defaults.document),
+            ("word", &mut self.word, defaults.word),
+            ("lines_above", &mut self.lines_above, defaults.lines_above),
+        ] {
+            if script.is_none() {
+                *script = default;
+            }
+            anyhow::ensure!(
+                script.is_some(),
+                "there are no built-in capture keys for {name}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Copies the text described by `load` to the clipboard.
+    pub fn copy(&self, keyboard: &mut dyn Keyboard, load: ClipboardLoad) -> anyhow::Result<()> {
+        let lines = match load {
+            ClipboardLoad::LinesAbove(lines) => lines,
+            _ => 1,
+        };
+        self.script(load)
+            .with_context(|| format!("there are no capture keys for {load:?}"))?
+            .run(keyboard, lines);
+        Ok(())
+    }
+
+    fn script(&self, load: ClipboardLoad) -> Option<&KeyScript> {
+        match load {
+            ClipboardLoad::Line => self.line.as_ref(),
+            ClipboardLoad::Selection => self.selection.as_ref(),
+            ClipboardLoad::Paragraph => self.paragraph.as_ref(),
+            ClipboardLoad::Document => self.document.as_ref(),
+            ClipboardLoad::Word => self.word.as_ref(),
+            ClipboardLoad::LinesAbove(_) => self.lines_above.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fakes::{KeyEvent, RecordingKeyboard};
+
+    const DEFAULT: &str = include_str!("capture/default.toml");
+    const MACOS: &str = include_str!("capture/macos.toml");
+
+    fn parse(defaults: &str) -> CaptureKeys {
+        let keys: CaptureKeys = toml::from_str(defaults).unwrap();
+        for script in [
+            &keys.line,
+            &keys.selection,
+            &keys.paragraph,
+            &keys.document,
+            &keys.word,
+            &keys.lines_above,
+        ] {
+            assert!(script
+                .as_ref()
+                .is_some_and(|script| !script.steps.is_empty()));
+        }
+        keys
+    }
+
+    fn run(defaults: &str, load: ClipboardLoad) -> Vec<KeyEvent> {
+        let keyboard = RecordingKeyboard::default();
+        parse(defaults).copy(&mut keyboard.clone(), load).unwrap();
+        keyboard.take_events()
+    }
+
+    /// Presses `key` with `modifiers` held, the way a [`KeyStep`] does.
+    fn chord(modifiers: &[Keycode], key: Keycode) -> Vec<KeyEvent> {
+        let down = modifiers.iter().copied().map(KeyEvent::Down);
+        let up = modifiers.iter().rev().copied().map(KeyEvent::Up);
+        down.chain([KeyEvent::Click(key)]).chain(up).collect()
+    }
+
+    #[test]
+    fn every_platform_has_keys_for_every_mode() {
+        parse(DEFAULT);
+        parse(MACOS);
+        CaptureKeys::default().add_defaults().unwrap();
+    }
+
+    #[test]
+    fn configured_keys_replace_the_defaults() {
+        let mut keys: CaptureKeys = toml::from_str("[line]\nsteps = [{ key = \"End\" }]").unwrap();
+        keys.add_defaults().unwrap();
+        assert_eq!(keys.line.unwrap().steps[0].key, Keycode::End);
+        assert!(keys.word.is_some());
+    }
+
+    fn default_line() -> Vec<KeyEvent> {
+        [
+            chord(&[Keycode::LShift], Keycode::Home),
+            chord(&[Keycode::LShift], Keycode::Home),
+            chord(&[Keycode::LControl], Keycode::C),
+            chord(&[], Keycode::Right),
+        ]
+        .concat()
+    }
+
+    fn macos_line() -> Vec<KeyEvent> {
+        [
+            chord(&[Keycode::Meta, Keycode::LShift], Keycode::Left),
+            chord(&[Keycode::Meta], Keycode::C),
+            chord(&[], Keycode::Right),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn line_selects_to_the_start_of_the_line() {
+        assert_eq!(run(DEFAULT, ClipboardLoad::Line), default_line());
+        assert_eq!(run(MACOS, ClipboardLoad::Line), macos_line());
+        // Command has to be held before Shift, and let go after it.
+        assert_eq!(
+            run(MACOS, ClipboardLoad::Line)[..5],
+            [
+                KeyEvent::Down(Keycode::Meta),
+                KeyEvent::Down(Keycode::LShift),
+                KeyEvent::Click(Keycode::Left),
+                KeyEvent::Up(Keycode::LShift),
+                KeyEvent::Up(Keycode::Meta),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_above_selects_up_once_per_line() {
+        let up = chord(&[Keycode::LShift], Keycode::Up);
+        assert_eq!(
+            run(DEFAULT, ClipboardLoad::LinesAbove(2)),
+            [up.clone(), up.clone(), default_line()].concat()
+        );
+        assert_eq!(
+            run(MACOS, ClipboardLoad::LinesAbove(2)),
+            [up.clone(), up, macos_line()].concat()
+        );
+    }
+}
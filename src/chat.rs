@@ -0,0 +1,106 @@
+//! Conversations held in the chat window, kept on disk so that they aren't
+//! lost when the window is closed.
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{command::ChatCommand, config};
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Role {
+    #[serde(rename = "user")]
+    User,
+    #[serde(rename = "assistant")]
+    Assistant,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Message {
+    pub role: Role,
+    pub text: String,
+}
+
+/// A conversation, saved as a JSON list of its messages.
+pub struct Chat {
+    path: PathBuf,
+    pub messages: Vec<Message>,
+}
+impl Chat {
+    /// Starts a new chat. It's saved in the data directory, named after when it started.
+    pub fn new() -> anyhow::Result<Self> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = config::data_dir()?
+            .join("chats")
+            .join(started.to_string())
+            .with_extension("json");
+        Ok(Self {
+            path,
+            messages: vec![],
+        })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).context("couldn't create chats dir")?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.messages)?)
+            .with_context(|| format!("couldn't save chat to {}", self.path.display()))
+    }
+
+    /// Writes the chat as Markdown next to where it's saved, returning where
+    /// it was written.
+    pub fn export(&self) -> anyhow::Result<PathBuf> {
+        let mut markdown = String::new();
+        for message in &self.messages {
+            let heading = match message.role {
+                Role::User => "You",
+                Role::Assistant => "Assistant",
+            };
+            markdown.push_str(&format!("## {heading}\n\n{}\n\n", message.text.trim()));
+        }
+
+        let path = self.path.with_extension("md");
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("couldn't create chats dir")?;
+        }
+        std::fs::write(&path, markdown)
+            .with_context(|| format!("couldn't export chat to {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Replaces the user's message at `index` with `text`, forgetting
+    /// everything that came after it.
+    pub fn edit(&mut self, index: usize, text: String) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.messages
+                .get(index)
+                .is_some_and(|message| message.role == Role::User),
+            "message {index} isn't one of the user's"
+        );
+        self.messages.truncate(index);
+        self.messages.push(Message {
+            role: Role::User,
+            text,
+        });
+        Ok(())
+    }
+
+    /// The prompt that gets the model to write the next reply.
+    pub fn prompt(&self, command: &ChatCommand) -> String {
+        let mut prompt = format!("{}\n", command.system);
+        for message in &self.messages {
+            let prefix = match message.role {
+                Role::User => &command.user,
+                Role::Assistant => &command.assistant,
+            };
+            prompt.push_str(&format!("{prefix}{}\n", message.text.trim()));
+        }
+        prompt.push_str(&command.assistant);
+        prompt
+    }
+}
@@ -1,12 +1,32 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use crate::keycode::Keycode;
 
+/// What to copy from the focused application before reading the clipboard.
+/// The keys used to copy each of these can be changed with `general.capture_keys`;
+/// the defaults are in `src/capture/`.
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ClipboardLoad {
+    /// The current line, up to the cursor.
+    #[serde(rename = "line", alias = "Line")]
     Line,
+    /// Whatever is selected, which is left selected.
+    #[serde(rename = "selection")]
+    Selection,
+    /// The current paragraph, up to the cursor.
+    #[serde(rename = "paragraph")]
+    Paragraph,
+    /// The whole document. This leaves the cursor at the end of it.
+    #[serde(rename = "document")]
+    Document,
+    /// The word before the cursor.
+    #[serde(rename = "word")]
+    Word,
+    /// This many lines above the current one, and the current line up to the cursor.
+    #[serde(rename = "lines-above")]
+    LinesAbove(usize),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -19,6 +39,9 @@ pub struct Clipboard {
 pub enum InputMethod {
     #[serde(rename = "single-line-ui")]
     SingleLineUi,
+    /// A resizable editor for longer prompts. Ctrl+Enter submits.
+    #[serde(rename = "multi-line-ui")]
+    MultiLineUi,
     #[serde(rename = "clipboard")]
     Clipboard(Clipboard),
 }
@@ -41,11 +64,103 @@ pub enum NewlineBehavior {
     ShiftEnter,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum OutputSink {
+    /// Type the text into the focused application as it is generated.
+    #[serde(rename = "type")]
+    Type,
+    /// Paste the text into the focused application once generation is done.
+    #[serde(rename = "paste")]
+    Paste,
+    /// Put the text on the clipboard once generation is done.
+    #[serde(rename = "clipboard")]
+    Clipboard,
+    /// Paste the text into the focused application in chunks as it is generated.
+    ///
+    /// This is much faster than typing and isn't affected by the keyboard layout,
+    /// but newlines are pasted as-is instead of following the newline behaviour.
+    #[serde(rename = "streaming-paste")]
+    StreamingPaste(Chunking),
+    /// Show the text in a window as it is generated, rendered as Markdown.
+    /// The window stays open until it's dismissed, and can be pinned on top.
+    #[serde(rename = "window")]
+    Window,
+    /// Append the text to a file as it is generated.
+    #[serde(rename = "file")]
+    File(PathBuf),
+    /// Print the text to stdout as it is generated.
+    #[serde(rename = "stdout")]
+    Stdout,
+}
+
+/// When to paste the text that has been generated so far.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Chunking {
+    /// After each complete word.
+    #[serde(rename = "word")]
+    Word,
+    /// After each complete line.
+    #[serde(rename = "line")]
+    Line,
+    /// Whenever this many milliseconds have passed since the last paste.
+    #[serde(rename = "interval")]
+    Interval(u64),
+}
+
+fn default_output() -> Vec<OutputSink> {
+    vec![OutputSink::Type]
+}
+
+fn default_history() -> bool {
+    true
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct GenerateCommand {
     pub input: InputMethod,
     pub mode: PromptMode,
     pub newline: NewlineBehavior,
+    #[serde(default = "default_output")]
+    pub output: Vec<OutputSink>,
+    /// Whether to remember prompts entered in the input window, so that they can
+    /// be recalled later. Turn this off for commands used with sensitive text.
+    #[serde(default = "default_history")]
+    pub history: bool,
+    /// Stream the output into a window first, where it can be edited and then
+    /// accepted, copied, regenerated or discarded. Only accepted text is sent
+    /// to the outputs.
+    #[serde(default)]
+    pub preview: bool,
+}
+
+fn default_chat_system() -> String {
+    "SYSTEM: You are a general AI assistant.".to_string()
+}
+
+fn default_chat_user() -> String {
+    "USER: ".to_string()
+}
+
+fn default_chat_assistant() -> String {
+    "ASSISTANT: ".to_string()
+}
+
+/// How to turn a conversation in the chat window into a prompt.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatCommand {
+    /// Put at the start of the prompt, on its own line.
+    #[serde(default = "default_chat_system")]
+    pub system: String,
+    /// Put before each of the user's messages. The reply is cut off if the
+    /// model starts writing the user's next message.
+    #[serde(default = "default_chat_user")]
+    pub user: String,
+    /// Put before each of the model's replies.
+    #[serde(default = "default_chat_assistant")]
+    pub assistant: String,
+    /// The model from `models` in the config to chat with, instead of the main one.
+    #[serde(default)]
+    pub model: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -54,24 +169,83 @@ pub enum CommandType {
     Generate(GenerateCommand),
     #[serde(rename = "cancel")]
     Cancel,
+    /// Remove the text inserted by the last generation.
+    #[serde(rename = "undo-last")]
+    UndoLast,
+    /// Remove the text inserted by the last generation and run its prompt again.
+    #[serde(rename = "regenerate")]
+    Regenerate,
+    /// Open a window listing every named generate command, and run the one
+    /// the user picks with the prompt they enter there.
+    #[serde(rename = "palette")]
+    Palette,
+    /// Open a window for holding a conversation with the model. Chats are
+    /// saved in the data directory, and can be exported as Markdown.
+    ///
+    /// The chat is a job like any other, so commands triggered while it's open
+    /// wait until it's closed, unless their policy is `replace`. The chat
+    /// window says when this happens.
+    #[serde(rename = "chat")]
+    Chat(ChatCommand),
+}
+
+/// What to do when a command is triggered while other jobs are running or queued.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Policy {
+    /// Run once everything before it has finished.
+    #[default]
+    #[serde(rename = "queue")]
+    Queue,
+    /// Cancel everything that's running or queued, then run.
+    #[serde(rename = "replace")]
+    Replace,
+    /// Don't run at all.
+    #[serde(rename = "ignore")]
+    Ignore,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Command {
+    /// The name shown in the command palette. Only named commands are listed there.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// What the command does, shown in the command palette.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The keys that trigger the command. Commands that are only run from the
+    /// command palette can leave this empty.
+    #[serde(default)]
     pub keys: HashSet<Keycode>,
     #[serde(rename = "type")]
     pub ty: CommandType,
+    #[serde(default)]
+    pub policy: Policy,
 }
 
 impl Command {
     pub fn new(keys: impl IntoIterator<Item = Keycode>, ty: CommandType) -> Self {
         Self {
+            name: None,
+            description: None,
             keys: keys.into_iter().collect(),
             ty,
+            policy: Policy::default(),
         }
     }
 
     pub fn is_pressed(&self, keycodes: &HashSet<Keycode>) -> bool {
-        keycodes.is_superset(&self.keys)
+        !self.keys.is_empty() && keycodes.is_superset(&self.keys)
+    }
+
+    /// The command's name if it has one, and its shortcut otherwise.
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.shortcut())
+    }
+
+    /// The keys for this command, formatted like `LControl+Apostrophe`.
+    pub fn shortcut(&self) -> String {
+        let mut keys: Vec<_> = self.keys.iter().map(|key| format!("{key:?}")).collect();
+        keys.sort();
+        keys.join("+")
     }
 }
@@ -1,12 +1,16 @@
-use std::{path::PathBuf, sync::OnceLock};
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
 
 use anyhow::Context;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    command::{Command, CommandType, GenerateCommand, InputMethod, NewlineBehavior, PromptMode},
+    capture::CaptureKeys,
+    command::{
+        Command, CommandType, GenerateCommand, InputMethod, NewlineBehavior, OutputSink, PromptMode,
+    },
     keycode::Keycode,
+    window,
 };
 
 const USE_WORKING_DIR: bool = true;
@@ -27,16 +31,33 @@ pub fn init() -> anyhow::Result<&'static Config> {
 
         config_dir.join("config.toml")
     };
-    let config = if config_path.exists() {
+    let mut config = if config_path.exists() {
         toml::from_str::<Config>(&std::fs::read_to_string(&config_path)?)?
     } else {
         Default::default()
     };
     std::fs::write(&config_path, toml::to_string_pretty(&config)?)?;
+    // This comes after writing the config, so that this platform's keys
+    // aren't saved as if they were the user's.
+    config.general.capture_keys.add_defaults()?;
 
     Ok(CONFIG.get_or_init(|| config))
 }
 
+/// The directory that alpa keeps its data in, like prompt history.
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    let data_dir = if USE_WORKING_DIR {
+        PathBuf::from("data")
+    } else {
+        ProjectDirs::from("org", "philpax", "alpa")
+            .context("couldn't get project dir")?
+            .data_dir()
+            .to_owned()
+    };
+    std::fs::create_dir_all(&data_dir).context("couldn't create data dir")?;
+    Ok(data_dir)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Config {
     #[serde(default)]
@@ -45,6 +66,10 @@ pub struct Config {
     pub general: General,
     #[serde(default)]
     pub model: Model,
+    /// Other models that can be picked with `/model <name>` in the input window.
+    /// They're loaded the first time they're used.
+    #[serde(default)]
+    pub models: BTreeMap<String, Model>,
     #[serde(default = "default_commands")]
     pub commands: Vec<Command>,
 }
@@ -60,6 +85,9 @@ fn default_commands() -> Vec<Command> {
                         .to_string(),
                 ),
                 newline: NewlineBehavior::Enter,
+                output: vec![OutputSink::Type],
+                history: true,
+                preview: false,
             }),
         ),
         Command::new([Keycode::Escape], CommandType::Cancel),
@@ -70,6 +98,48 @@ fn default_commands() -> Vec<Command> {
 pub struct Window {
     pub width: u32,
     pub height: u32,
+    #[serde(default = "default_display_width")]
+    pub display_width: u32,
+    #[serde(default = "default_display_height")]
+    pub display_height: u32,
+    /// The initial size of the multi-line editor, which the user can resize
+    /// within the limits below.
+    #[serde(default = "default_multiline_width")]
+    pub multiline_width: u32,
+    #[serde(default = "default_multiline_height")]
+    pub multiline_height: u32,
+    #[serde(default = "default_multiline_min_width")]
+    pub multiline_min_width: u32,
+    #[serde(default = "default_multiline_min_height")]
+    pub multiline_min_height: u32,
+    #[serde(default = "default_multiline_max_width")]
+    pub multiline_max_width: u32,
+    #[serde(default = "default_multiline_max_height")]
+    pub multiline_max_height: u32,
+    #[serde(default = "default_chat_width")]
+    pub chat_width: u32,
+    #[serde(default = "default_chat_height")]
+    pub chat_height: u32,
+    /// How long the single-line input window can go without responding before
+    /// it's assumed to have hung and is given up on. The user can take as long
+    /// as they like. 0 waits forever.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Whether to show a window with a progress bar while the model loads.
+    #[serde(default)]
+    pub show_load_progress: bool,
+    /// Keep the input window running in the background so that it appears
+    /// instantly. If it can't take focus when shown, a new window is started
+    /// for each prompt instead.
+    #[serde(default)]
+    pub persistent: bool,
+    /// How to draw windows: `auto` uses the GPU if possible and draws on the
+    /// CPU otherwise.
+    #[serde(default)]
+    pub renderer: window::Renderer,
+    /// Where windows appear, and their theme, opacity, fonts and padding.
+    #[serde(default)]
+    pub appearance: window::Appearance,
 }
 
 impl Default for Window {
@@ -77,12 +147,79 @@ impl Default for Window {
         Self {
             width: 640,
             height: 32,
+            display_width: default_display_width(),
+            display_height: default_display_height(),
+            multiline_width: default_multiline_width(),
+            multiline_height: default_multiline_height(),
+            multiline_min_width: default_multiline_min_width(),
+            multiline_min_height: default_multiline_min_height(),
+            multiline_max_width: default_multiline_max_width(),
+            multiline_max_height: default_multiline_max_height(),
+            chat_width: default_chat_width(),
+            chat_height: default_chat_height(),
+            timeout_secs: default_timeout_secs(),
+            show_load_progress: false,
+            persistent: false,
+            renderer: Default::default(),
+            appearance: Default::default(),
         }
     }
 }
 
+fn default_display_width() -> u32 {
+    640
+}
+
+fn default_display_height() -> u32 {
+    320
+}
+
+fn default_multiline_width() -> u32 {
+    640
+}
+
+fn default_multiline_height() -> u32 {
+    240
+}
+
+fn default_multiline_min_width() -> u32 {
+    320
+}
+
+fn default_multiline_min_height() -> u32 {
+    96
+}
+
+fn default_multiline_max_width() -> u32 {
+    1600
+}
+
+fn default_multiline_max_height() -> u32 {
+    1200
+}
+
+fn default_chat_width() -> u32 {
+    560
+}
+
+fn default_chat_height() -> u32 {
+    640
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct General {}
+pub struct General {
+    /// Show errors from commands in a popup window, as well as on stderr.
+    #[serde(default)]
+    pub error_popups: bool,
+    /// The key scripts used to copy text for commands that read their prompt
+    /// from the clipboard, to replace the defaults for this platform.
+    #[serde(default)]
+    pub capture_keys: CaptureKeys,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Model {
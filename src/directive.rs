@@ -0,0 +1,201 @@
+//! Directives at the start of a prompt, like `/t 0.2`, that change how that
+//! one prompt is run.
+
+use anyhow::Context;
+
+/// The directives that can be used, and what they're for.
+pub const DIRECTIVES: &[(&str, &str)] = &[
+    ("t", "sampling temperature"),
+    ("model", "named model from `models` in the config"),
+    ("max", "maximum number of tokens to generate"),
+    ("cmd", "named command to run instead"),
+];
+
+/// The overrides given by a prompt's directives.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Directives {
+    pub temperature: Option<f32>,
+    pub model: Option<String>,
+    pub max_tokens: Option<usize>,
+    pub command: Option<String>,
+}
+
+/// Splits the directives off the start of `text`, returning them along with
+/// the rest of the prompt.
+///
+/// Parsing stops at the first word that isn't a directive, so a prompt like
+/// `/usr/bin is for...` is left alone. Fails if a directive's value is missing
+/// or invalid.
+pub fn parse(text: &str) -> anyhow::Result<(Directives, &str)> {
+    let mut directives = Directives::default();
+    let mut rest = text.trim_start();
+    while let Some(stripped) = rest.strip_prefix('/') {
+        let (name, after) = split_word(stripped);
+        if !DIRECTIVES.iter().any(|(directive, _)| *directive == name) {
+            break;
+        }
+        let (value, after) = split_word(after.trim_start());
+        anyhow::ensure!(!value.is_empty(), "/{name} needs a value");
+
+        match name {
+            "t" => {
+                directives.temperature = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("/t needs a number, not {value:?}"))?,
+                )
+            }
+            "max" => {
+                directives.max_tokens = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("/max needs a whole number, not {value:?}"))?,
+                )
+            }
+            "model" => directives.model = Some(value.to_string()),
+            "cmd" => directives.command = Some(value.to_string()),
+            _ => unreachable!("{name} is in DIRECTIVES but isn't handled"),
+        }
+        rest = after.trim_start();
+    }
+    Ok((directives, rest))
+}
+
+/// A suggestion for what to type next.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Completion {
+    /// These characters would finish the directive or value being typed.
+    Insert(String),
+    /// A directive's value is expected, and this is what it's for.
+    Hint(&'static str),
+}
+
+/// Suggests how to finish the directive or value being typed at the end of `text`.
+///
+/// `models` and `commands` are the names that `/model` and `/cmd` accept.
+pub fn complete(text: &str, models: &[String], commands: &[String]) -> Option<Completion> {
+    let (before, last) = text
+        .trim_start()
+        .rsplit_once(char::is_whitespace)
+        .unwrap_or(("", text.trim_start()));
+
+    // Everything before the word being typed has to be directives and their values.
+    let mut expecting_value = None;
+    for word in before.split_whitespace() {
+        if expecting_value.take().is_none() {
+            let name = word.strip_prefix('/')?;
+            expecting_value = Some(
+                DIRECTIVES
+                    .iter()
+                    .find(|(directive, _)| *directive == name)?,
+            );
+        }
+    }
+
+    let (prefix, candidates): (&str, Vec<&str>) = match expecting_value {
+        None => (
+            last.strip_prefix('/')?,
+            DIRECTIVES.iter().map(|(name, _)| *name).collect(),
+        ),
+        Some(("model", _)) => (last, models.iter().map(String::as_str).collect()),
+        Some(("cmd", _)) => (last, commands.iter().map(String::as_str).collect()),
+        Some(_) => (last, vec![]),
+    };
+    let candidate = candidates
+        .into_iter()
+        .find(|candidate| candidate.len() > prefix.len() && candidate.starts_with(prefix));
+    match (candidate, expecting_value) {
+        (Some(candidate), _) => Some(Completion::Insert(candidate[prefix.len()..].to_string())),
+        (None, Some(&(_, description))) if last.is_empty() => Some(Completion::Hint(description)),
+        _ => None,
+    }
+}
+
+/// Splits `text` at its first whitespace.
+fn split_word(text: &str) -> (&str, &str) {
+    text.split_once(char::is_whitespace).unwrap_or((text, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_splits_off_directives() {
+        let (directives, rest) = parse("/t 0.2 /max 10 /model big /cmd code fix this").unwrap();
+        assert_eq!(
+            directives,
+            Directives {
+                temperature: Some(0.2),
+                model: Some("big".to_string()),
+                max_tokens: Some(10),
+                command: Some("code".to_string()),
+            }
+        );
+        assert_eq!(rest, "fix this");
+    }
+
+    #[test]
+    fn parse_leaves_other_slashes_alone() {
+        let (directives, rest) = parse("/usr/bin is for binaries").unwrap();
+        assert_eq!(directives, Directives::default());
+        assert_eq!(rest, "/usr/bin is for binaries");
+
+        let (directives, rest) = parse("/t 0.5 /usr/bin").unwrap();
+        assert_eq!(directives.temperature, Some(0.5));
+        assert_eq!(rest, "/usr/bin");
+    }
+
+    #[test]
+    fn parse_needs_a_value() {
+        assert!(parse("/t").is_err());
+        assert!(parse("/t  ").is_err());
+        assert!(parse("/t warm").is_err());
+        assert!(parse("/max 1.5").is_err());
+    }
+
+    #[test]
+    fn parse_allows_extra_spaces() {
+        let (directives, rest) = parse("  /t  0.2   hello").unwrap();
+        assert_eq!(directives.temperature, Some(0.2));
+        assert_eq!(rest, "hello");
+    }
+
+    fn complete(text: &str) -> Option<Completion> {
+        super::complete(text, &["llama".to_string()], &["code".to_string()])
+    }
+
+    fn insert(text: &str) -> Option<Completion> {
+        Some(Completion::Insert(text.to_string()))
+    }
+
+    #[test]
+    fn complete_finishes_directives_and_values() {
+        assert_eq!(complete("/mo"), insert("del"));
+        assert_eq!(complete("/model "), insert("llama"));
+        assert_eq!(complete("/model ll"), insert("ama"));
+        assert_eq!(complete("/cmd c"), insert("ode"));
+        assert_eq!(
+            complete("/t "),
+            Some(Completion::Hint("sampling temperature"))
+        );
+        assert_eq!(complete("/model llama"), None);
+    }
+
+    #[test]
+    fn complete_carries_on_after_a_value() {
+        assert_eq!(complete("/t 0.2 /ma"), insert("x"));
+        assert_eq!(complete("/t  0.2  /ma"), insert("x"));
+        assert_eq!(
+            complete("/t  "),
+            Some(Completion::Hint("sampling temperature"))
+        );
+    }
+
+    #[test]
+    fn complete_stops_at_the_prompt() {
+        assert_eq!(complete("hello /mo"), None);
+        assert_eq!(complete("/t 0.2 hello /mo"), None);
+        assert_eq!(complete("/usr/bin"), None);
+    }
+}
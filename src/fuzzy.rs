@@ -0,0 +1,75 @@
+//! Fuzzy matching, for finding things from a few typed characters.
+
+/// Scores how well `query` matches `candidate`, or returns `None` if it doesn't.
+///
+/// Every character of `query` has to appear in `candidate` in order, ignoring
+/// case. Matches score higher when the characters are next to each other or at
+/// the start of words.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut query_chars = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+
+    for (index, c) in candidate.chars().enumerate() {
+        let Some(&wanted) = query_chars.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(wanted)) {
+            query_chars.next();
+            score += 1;
+            if last_match.is_some_and(|last| last + 1 == index) {
+                score += 5;
+            }
+            if !previous.is_some_and(|p| p.is_alphanumeric()) {
+                score += 3;
+            }
+            if let Some(last) = last_match {
+                // Small gaps are better than big ones.
+                score -= (index - last - 1).min(5) as i64;
+            }
+            last_match = Some(index);
+        }
+        previous = Some(c);
+    }
+
+    query_chars.peek().is_none().then_some(score)
+}
+
+/// Returns the indices of the `candidates` that match `query`, best match first.
+/// Equally good matches stay in the order they were given in.
+pub fn rank<'a>(query: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut matches: Vec<(usize, i64)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| Some((index, score(query, candidate)?)))
+        .collect();
+    matches.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_puts_the_best_match_first() {
+        assert_eq!(rank("gen", ["chat", "regenerate", "generate"]), [2, 1]);
+    }
+
+    #[test]
+    fn rank_ignores_case() {
+        assert_eq!(rank("GEN", ["generate"]), [0]);
+    }
+
+    #[test]
+    fn rank_keeps_the_order_of_equal_matches() {
+        assert_eq!(rank("a", ["ba", "ca", "xyz"]), [0, 1]);
+        assert_eq!(rank("", ["b", "a"]), [0, 1]);
+    }
+
+    #[test]
+    fn rank_needs_every_character_in_order() {
+        assert!(rank("neg", ["generate"]).is_empty());
+    }
+}
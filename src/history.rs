@@ -0,0 +1,83 @@
+//! Prompts that have been entered for each command, kept on disk so that they
+//! can be recalled in the input window.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+
+use crate::config;
+
+/// How many prompts are kept for each command. Older prompts are forgotten.
+const MAX_ENTRIES: usize = 1000;
+
+/// The prompts entered for one command, oldest first.
+///
+/// Each prompt is stored as one line of JSON, so that prompts with newlines in
+/// them survive the trip.
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+}
+impl History {
+    /// Loads the history for the command identified by `key`.
+    pub fn load(key: &str) -> anyhow::Result<Self> {
+        let file_name: String = key
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = config::data_dir()?
+            .join("history")
+            .join(file_name)
+            .with_extension("jsonl");
+
+        let mut entries = vec![];
+        if path.exists() {
+            let file = File::open(&path)
+                .with_context(|| format!("couldn't open history file {}", path.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                match serde_json::from_str(&line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(err) => eprintln!("Skipping history entry {line:?}: {err}"),
+                }
+            }
+        }
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Remembers `prompt`, unless it's the same as the last one.
+    pub fn add(&mut self, prompt: &str) -> anyhow::Result<()> {
+        if prompt.is_empty() || self.entries.last().is_some_and(|last| last == prompt) {
+            return Ok(());
+        }
+        self.entries.push(prompt.to_string());
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).context("couldn't create history dir")?;
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            // Rewrite the file without the oldest entries.
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+            let mut file = File::create(&self.path)?;
+            for entry in &self.entries {
+                writeln!(file, "{}", serde_json::to_string(entry)?)?;
+            }
+        } else {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            writeln!(file, "{}", serde_json::to_string(prompt)?)?;
+        }
+        Ok(())
+    }
+}
@@ -1,246 +1,209 @@
 use crate::{
-    command::{ClipboardLoad, CommandType, InputMethod, NewlineBehavior, PromptMode},
+    cancel::CancellationToken,
+    command::{Command, CommandType, Policy},
     config::{self, Config},
-    keycode::Keycode,
-    window,
-};
-use device_query::DeviceQuery;
-use enigo::{Enigo, Key, KeyboardControllable};
-use std::{
-    collections::HashSet,
-    convert::Infallible,
-    env, process,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
-    },
+    ipc, output,
 };
+use std::collections::VecDeque;
+
+mod hotkeys;
+mod popup;
+mod preview;
+mod worker;
+
+use hotkeys::KeyState;
+use worker::WorkerEvent;
+
+/// Everything the host's core reacts to.
+enum Event {
+    /// The keys for a command were pressed.
+    Triggered(&'static Command),
+    /// The worker has something to report.
+    Worker(WorkerEvent),
+}
 
-pub(super) fn main() -> anyhow::Result<()> {
-    let enigo = Arc::new(Mutex::new(Enigo::new()));
-    let mut arboard = arboard::Clipboard::new()?;
+/// A command that has been triggered, along with the token used to cancel it.
+#[derive(Clone)]
+struct Job {
+    command: &'static Command,
+    token: CancellationToken,
+}
 
+pub(super) async fn main() -> anyhow::Result<()> {
     let config = config::init()?;
 
-    let model = llm::load_dynamic(
-        Some(config.model.architecture()?),
-        // TODO: support others
-        &config.model.path,
-        llm::TokenizerSource::Embedded,
-        llm::ModelParameters {
-            prefer_mmap: config.model.prefer_mmap,
-            context_size: config.model.context_token_length,
-            use_gpu: config.model.use_gpu,
-            ..Default::default()
-        },
-        llm::load_progress_callback_stdout,
-    )?;
-
-    let (command_tx, command_rx) = flume::bounded(1);
-    let is_generating = Arc::new(AtomicBool::new(false));
-    let cancel_immediately = Arc::new(AtomicBool::new(false));
-    let any_keys_pressed = Arc::new(AtomicBool::new(false));
-
-    let _input_thread = std::thread::spawn({
-        let is_generating = is_generating.clone();
-        let cancel_immediately = cancel_immediately.clone();
-        let any_keys_pressed = any_keys_pressed.clone();
-        move || {
-            let device_state = device_query::DeviceState::new();
-            // Use to prevent the same command being sent multiple times during a generation attempt
-            let mut last_pressed = None;
-            loop {
-                let new_keycodes =
-                    HashSet::from_iter(device_state.get_keys().into_iter().map(Keycode::from));
-                any_keys_pressed.store(!new_keycodes.is_empty(), Ordering::SeqCst);
-
-                if !is_generating.load(Ordering::SeqCst) {
-                    last_pressed = None;
-                }
-
-                let mut commands_to_process = vec![];
-                for command in &config.commands {
-                    if command.is_pressed(&new_keycodes) {
-                        commands_to_process.push(command);
-                        continue;
-                    }
-                }
-                commands_to_process.sort_by_key(|cmd| -(cmd.keys.len() as isize));
-
-                if let Some(command) = commands_to_process.first() {
-                    match &command.ty {
-                        CommandType::Generate(generate) => {
-                            if last_pressed != Some(generate) {
-                                command_tx.send(generate).unwrap();
-                                last_pressed = Some(generate);
-                            }
-                        }
-
-                        CommandType::Cancel => {
-                            cancel_immediately.store(true, Ordering::SeqCst);
-                        }
-                    }
-                }
-
-                std::thread::sleep(std::time::Duration::from_millis(10));
-            }
+    let (event_tx, event_rx) = flume::unbounded();
+    let (job_tx, job_rx) = flume::unbounded();
+    let (notice_tx, notice_rx) = flume::unbounded();
+    let keys = KeyState::default();
+
+    let _hotkey_thread = hotkeys::spawn(config, keys.clone(), event_tx.clone());
+    let _worker_thread = worker::spawn(config, keys, job_rx, notice_rx, event_tx);
+
+    let mut core = Core {
+        config,
+        job_tx,
+        queued_notices: notice_tx,
+        ready: false,
+        running: None,
+        queue: VecDeque::new(),
+    };
+    while let Ok(event) = event_rx.recv_async().await {
+        match event {
+            Event::Triggered(command) => core.triggered(command),
+            Event::Worker(event) => core.worker_event(event)?,
         }
-    });
+    }
 
-    println!("Ready to go!");
+    Ok(())
+}
 
-    while let Ok(command) = command_rx.recv() {
-        is_generating.store(true, Ordering::SeqCst);
+/// Decides which jobs run and when, and hands them to the worker one at a time.
+struct Core {
+    config: &'static Config,
+    job_tx: flume::Sender<Job>,
+    /// Tells the chat window, if one is open, about jobs waiting for it to close.
+    queued_notices: flume::Sender<String>,
+    /// Whether the worker has finished starting up.
+    ready: bool,
+    running: Option<Running>,
+    queue: VecDeque<Job>,
+}
+struct Running {
+    job: Job,
+    /// The number of tokens generated so far.
+    tokens: usize,
+}
+impl Core {
+    fn triggered(&mut self, command: &'static Command) {
+        match &command.ty {
+            CommandType::Generate(_)
+            | CommandType::UndoLast
+            | CommandType::Regenerate
+            | CommandType::Palette
+            | CommandType::Chat(_) => self.submit(command),
+            // The windows use Escape, the default for this, for things of their own.
+            CommandType::Cancel if ipc::window_has_focus() => {}
+            CommandType::Cancel => self.cancel(),
+        }
+    }
 
-        let prompt = match &command.input {
-            InputMethod::SingleLineUi => ask_for_singleline_input(config)?,
-            InputMethod::Clipboard(clipboard) => {
-                // HACK: wait for all keys to be released
-                while any_keys_pressed.load(Ordering::SeqCst) {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
+    /// Queues a triggered command, following the command's [`Policy`].
+    fn submit(&mut self, command: &'static Command) {
+        let label = command.label();
+        let busy = self.queue.len() + usize::from(self.running.is_some());
+        if busy > 0 {
+            // A chat can stay open for hours, and the console may not be visible,
+            // so say in the chat window why nothing is happening.
+            let chat_notice = match command.policy {
+                Policy::Queue => Some(format!("{label} will run once this chat is closed")),
+                Policy::Replace => None,
+                Policy::Ignore => Some(format!("Ignoring {label} while this chat is open")),
+            };
+            if let Some(notice) = chat_notice.filter(|_| self.chat_is_running()) {
+                self.queued_notices.send(notice).ok();
+            }
 
-                let old_clipboard = if clipboard.load.is_some() {
-                    arboard.get_text().ok()
-                } else {
-                    None
-                };
-
-                #[allow(clippy::single_match)]
-                match clipboard.load {
-                    Some(ClipboardLoad::Line) => {
-                        let mut enigo = enigo.lock().unwrap();
-                        #[cfg(target_os = "macos")]
-                        {
-                            // TODO: fix this. It doesn't seem to actually work - Meta
-                            // behaves like LCtrl?
-
-                            // Make the selection
-                            enigo.key_down(Key::Meta);
-                            enigo.key_down(Key::LShift);
-                            enigo.key_click(Key::LeftArrow);
-                            enigo.key_up(Key::LShift);
-                            enigo.key_up(Key::Meta);
-
-                            // Copy it
-                            enigo.key_down(Key::Meta);
-                            enigo.key_sequence("C");
-                            enigo.key_up(Key::Meta);
-
-                            // Deselect
-                            enigo.key_click(Key::RightArrow);
-                        }
-                        #[cfg(not(target_os = "macos"))]
-                        {
-                            // Make the selection, hitting home twice to ensure we grab the whole line
-                            enigo.key_down(Key::LShift);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-                            enigo.key_click(Key::Home);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-                            enigo.key_click(Key::Home);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-                            enigo.key_up(Key::LShift);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-
-                            // Copy it
-                            enigo.key_down(Key::LControl);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-                            enigo.key_click(Key::C);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-                            enigo.key_up(Key::LControl);
-                            std::thread::sleep(std::time::Duration::from_millis(5));
-
-                            // Deselect
-                            enigo.key_click(Key::RightArrow);
-                        }
-                    }
-                    None => {}
+            match command.policy {
+                Policy::Queue => println!("Queueing {label} behind {busy} job(s)"),
+                Policy::Replace => {
+                    println!("Replacing {busy} job(s) with {label}");
+                    self.cancel();
                 }
-
-                let text = arboard.get_text()?;
-                if let Some(old_clipboard) = old_clipboard {
-                    arboard.set_text(old_clipboard)?;
+                Policy::Ignore => {
+                    println!("Ignoring {label}: {busy} job(s) already running or queued");
+                    return;
                 }
-                text
             }
-        };
+        }
 
-        if prompt.is_empty() {
-            continue;
+        self.queue.push_back(Job {
+            command,
+            token: CancellationToken::default(),
+        });
+        self.dispatch();
+    }
+
+    fn chat_is_running(&self) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(|running| matches!(running.job.command.ty, CommandType::Chat(_)))
+    }
+
+    /// Cancels the running job and drops everything that's queued.
+    fn cancel(&mut self) {
+        if let Some(running) = &self.running {
+            if !running.job.token.is_cancelled() {
+                println!("Cancelling {}", running.job.command.label());
+                running.job.token.cancel();
+            }
+        }
+        for job in self.queue.drain(..) {
+            println!("Dropping queued {}", job.command.label());
         }
+    }
 
-        let new_prompt = match &command.mode {
-            PromptMode::Autocomplete => prompt,
-            PromptMode::Prompt(template) => template.replace("{{PROMPT}}", &prompt),
+    /// Hands the next queued job to the worker if it isn't busy.
+    fn dispatch(&mut self) {
+        if !self.ready || self.running.is_some() {
+            return;
+        }
+        let Some(job) = self.queue.pop_front() else {
+            return;
         };
 
-        let cancel_immediately = cancel_immediately.clone();
-        let enigo = enigo.clone();
-        model.start_session(Default::default()).infer(
-            model.as_ref(),
-            &mut rand::thread_rng(),
-            &llm::InferenceRequest {
-                prompt: (&new_prompt).into(),
-                // TODO: expose sampler
-                parameters: &llm::InferenceParameters::default(),
-                play_back_previous_tokens: false,
-                maximum_token_count: None,
-            },
-            &mut Default::default(),
-            move |tok| {
-                if cancel_immediately.load(Ordering::SeqCst) {
-                    cancel_immediately.store(false, Ordering::SeqCst);
-                    return Ok(llm::InferenceFeedback::Halt);
-                }
+        println!(
+            "Running {}; {} job(s) queued",
+            job.command.label(),
+            self.queue.len()
+        );
+        // The worker only stops when this end is dropped, so this can't fail.
+        self.job_tx.send(job.clone()).unwrap();
+        self.running = Some(Running { job, tokens: 0 });
+    }
 
-                let mut feedback = llm::InferenceFeedback::Continue;
-
-                if let llm::InferenceResponse::InferredToken(t) = tok {
-                    let mut enigo = enigo.lock().unwrap();
-                    let mut first = true;
-                    for line in t.lines() {
-                        if !first || line.is_empty() {
-                            match command.newline {
-                                NewlineBehavior::Stop => {
-                                    feedback = llm::InferenceFeedback::Halt;
-                                }
-                                NewlineBehavior::Enter => {
-                                    enigo.key_click(Key::Return);
-                                }
-                                NewlineBehavior::ShiftEnter => {
-                                    enigo.key_down(Key::Shift);
-                                    enigo.key_click(Key::Return);
-                                    enigo.key_up(Key::Shift);
-                                }
-                            }
-                        }
-
-                        first = false;
-                        enigo.key_sequence(line);
-
-                        if matches!(feedback, llm::InferenceFeedback::Halt) {
-                            break;
-                        }
-                    }
+    fn worker_event(&mut self, event: WorkerEvent) -> anyhow::Result<()> {
+        match event {
+            WorkerEvent::Ready => {
+                println!("Ready to go!");
+                self.ready = true;
+            }
+            WorkerEvent::Status(message) => println!("{message}"),
+            WorkerEvent::Token => {
+                if let Some(running) = &mut self.running {
+                    running.tokens += 1;
                 }
-                Ok::<_, Infallible>(feedback)
-            },
-        )?;
+            }
+            WorkerEvent::Finished(result) => {
+                let label = self
+                    .running
+                    .take()
+                    .map(|running| {
+                        let label = running.job.command.label();
+                        println!("Finished {label} after {} token(s)", running.tokens);
+                        label
+                    })
+                    .unwrap_or_default();
+                if let Err(err) = result {
+                    self.report_error(&label, &err);
+                }
+            }
+            WorkerEvent::Failed(err) => return Err(err),
+        }
 
-        is_generating.store(false, Ordering::SeqCst);
+        self.dispatch();
+        Ok(())
     }
 
-    Ok(())
-}
-
-fn ask_for_singleline_input(config: &Config) -> anyhow::Result<String> {
-    let request = serde_json::to_string(&window::Args {
-        width: config.window.width,
-        height: config.window.height,
-    })?;
+    /// Tells the user that a command failed. The host carries on regardless.
+    fn report_error(&self, label: &str, err: &anyhow::Error) {
+        eprintln!("Error while running {label}: {err:?}");
 
-    let output = process::Command::new(env::current_exe()?)
-        .arg(request)
-        .output()?;
-    Ok(String::from_utf8(output.stdout)?)
+        if self.config.general.error_popups {
+            let message = format!("Error while running {label}:\n\n{err:#}");
+            if let Err(err) = output::show_in_window(self.config, &message) {
+                eprintln!("Couldn't show the error in a window: {err:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use device_query::DeviceQuery;
+
+use super::Event;
+use crate::{command::Command, config::Config, keycode::Keycode};
+
+/// What the hotkey thread knows about the keyboard, shared with the worker.
+#[derive(Clone, Default)]
+pub(super) struct KeyState {
+    any_pressed: Arc<AtomicBool>,
+    /// Set while the worker is sending keypresses of its own.
+    outputting: Arc<AtomicBool>,
+    /// Set when the user presses a key after alpa has finished outputting text.
+    typed_since_output: Arc<AtomicBool>,
+}
+impl KeyState {
+    pub fn wait_for_release(&self) {
+        // HACK: wait for all keys to be released
+        while self.any_pressed.load(Ordering::SeqCst) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    pub fn typed_since_output(&self) -> bool {
+        self.typed_since_output.load(Ordering::SeqCst)
+    }
+
+    /// Marks the start of a job, during which keypresses aren't attributed to the user.
+    pub fn start_output(&self) {
+        self.outputting.store(true, Ordering::SeqCst);
+    }
+
+    /// Marks that the job has inserted text, so that only keys pressed after
+    /// this count as typing since the output.
+    pub fn output_injected(&self) {
+        self.typed_since_output.store(false, Ordering::SeqCst);
+    }
+
+    /// Marks the end of a job. Keypresses from here on are attributed to the user.
+    pub fn finish_output(&self) {
+        self.outputting.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Spawns the thread that watches the keyboard for commands.
+pub(super) fn spawn(
+    config: &'static Config,
+    keys: KeyState,
+    event_tx: flume::Sender<Event>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let device_state = device_query::DeviceState::new();
+        let mut watcher = Watcher::new(&config.commands, keys);
+        loop {
+            let new_keycodes: HashSet<Keycode> =
+                HashSet::from_iter(device_state.get_keys().into_iter().map(Keycode::from));
+            if let Some(command) = watcher.update(&new_keycodes) {
+                if event_tx.send(Event::Triggered(command)).is_err() {
+                    // The host has shut down.
+                    return;
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    })
+}
+
+/// Turns the keys held down at each poll into triggered commands, and keeps
+/// the [`KeyState`] up to date.
+struct Watcher<'a> {
+    commands: &'a [Command],
+    keys: KeyState,
+    /// Commands are triggered when their keys go down, not for as long as they're held
+    last_pressed: Option<&'a Command>,
+    /// The last command triggered, until all keys are released. Its keys
+    /// don't count as the user typing.
+    triggered: Option<&'a Command>,
+}
+impl<'a> Watcher<'a> {
+    fn new(commands: &'a [Command], keys: KeyState) -> Self {
+        Self {
+            commands,
+            keys,
+            last_pressed: None,
+            triggered: None,
+        }
+    }
+
+    /// Records that `pressed` are held down, returning the command they trigger, if any.
+    fn update(&mut self, pressed: &HashSet<Keycode>) -> Option<&'a Command> {
+        self.keys
+            .any_pressed
+            .store(!pressed.is_empty(), Ordering::SeqCst);
+
+        let mut commands_to_process: Vec<_> = self
+            .commands
+            .iter()
+            .filter(|command| command.is_pressed(pressed))
+            .collect();
+        commands_to_process.sort_by_key(|cmd| -(cmd.keys.len() as isize));
+
+        let command = commands_to_process.first().copied();
+        let newly_pressed = command.filter(|&c| self.last_pressed != Some(c));
+        if newly_pressed.is_some() {
+            self.triggered = newly_pressed;
+        }
+        self.last_pressed = command;
+        if pressed.is_empty() {
+            self.triggered = None;
+        }
+
+        // Anything pressed while we're outputting is likely to be our own output
+        if !self.keys.outputting.load(Ordering::SeqCst) && self.is_typing(pressed) {
+            self.keys.typed_since_output.store(true, Ordering::SeqCst);
+        }
+
+        newly_pressed
+    }
+
+    /// Whether `pressed` is the user typing, rather than the keys of a command.
+    /// Keys that could still become a command, like the modifiers of a chord
+    /// pressed before the rest of it, don't count.
+    fn is_typing(&self, pressed: &HashSet<Keycode>) -> bool {
+        let part_of_command = self
+            .commands
+            .iter()
+            .any(|command| !command.keys.is_empty() && pressed.is_subset(&command.keys));
+        !pressed.is_empty()
+            && !part_of_command
+            && pressed.iter().any(|key| {
+                !self
+                    .triggered
+                    .is_some_and(|command| command.keys.contains(key))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::CommandType;
+
+    fn undo() -> Vec<Command> {
+        vec![Command::new(
+            [Keycode::LControl, Keycode::LShift, Keycode::Z],
+            CommandType::UndoLast,
+        )]
+    }
+
+    fn press(watcher: &mut Watcher, keys: &[Keycode]) -> bool {
+        watcher.update(&keys.iter().copied().collect()).is_some()
+    }
+
+    fn after_output(commands: &[Command]) -> Watcher {
+        let keys = KeyState::default();
+        keys.start_output();
+        keys.output_injected();
+        keys.finish_output();
+        Watcher::new(commands, keys)
+    }
+
+    #[test]
+    fn pressing_a_chord_one_key_at_a_time_isnt_typing() {
+        let commands = undo();
+        let mut watcher = after_output(&commands);
+        assert!(!press(&mut watcher, &[Keycode::LControl]));
+        assert!(!press(&mut watcher, &[Keycode::LControl, Keycode::LShift]));
+        assert!(press(
+            &mut watcher,
+            &[Keycode::LControl, Keycode::LShift, Keycode::Z]
+        ));
+        assert!(!press(&mut watcher, &[Keycode::LControl]));
+        assert!(!press(&mut watcher, &[]));
+        assert!(!watcher.keys.typed_since_output());
+    }
+
+    #[test]
+    fn other_keys_are_typing() {
+        let commands = undo();
+        let mut watcher = after_output(&commands);
+        press(&mut watcher, &[Keycode::LControl]);
+        press(&mut watcher, &[Keycode::LControl, Keycode::A]);
+        assert!(watcher.keys.typed_since_output());
+    }
+
+    #[test]
+    fn keys_pressed_while_holding_a_command_are_typing() {
+        let commands = undo();
+        let mut watcher = after_output(&commands);
+        press(
+            &mut watcher,
+            &[Keycode::LControl, Keycode::LShift, Keycode::Z],
+        );
+        assert!(!watcher.keys.typed_since_output());
+        press(
+            &mut watcher,
+            &[Keycode::LControl, Keycode::LShift, Keycode::Z, Keycode::A],
+        );
+        assert!(watcher.keys.typed_since_output());
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    cancel::CancellationToken,
+    config::Config,
+    ipc::{HostMessage, WindowMessage, WindowProcess},
+    window,
+};
+
+/// How long a persistent window has to take focus after being shown.
+const FOCUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What the user submitted in a window.
+pub(super) enum Answer {
+    Text(String),
+    /// A command picked from the palette, and the prompt for it.
+    Pick {
+        command: usize,
+        text: String,
+    },
+}
+impl Answer {
+    pub fn into_text(self) -> String {
+        match self {
+            Answer::Text(text) | Answer::Pick { text, .. } => text,
+        }
+    }
+}
+
+/// Runs a window with `args`, and waits for the user to submit something.
+/// `setup` is sent to the window before the user sees it, and whatever
+/// `on_text` returns for the user's input is sent as they type.
+///
+/// Returns what was submitted, or `None` if the user closed the window or the
+/// job was cancelled, in which case the window is closed. Fails if a
+/// single-line window goes quiet for longer than the configured timeout, or
+/// the window reports or exits with an error; the window's stderr is included
+/// in the error.
+pub(super) fn run(
+    config: &Config,
+    args: &window::Args,
+    setup: &[HostMessage],
+    on_text: &dyn Fn(&str) -> Option<HostMessage>,
+    token: &CancellationToken,
+) -> anyhow::Result<Option<Answer>> {
+    let mut window = WindowProcess::spawn(args)?;
+    send_all(&mut window, setup)?;
+
+    // The editor and palette can take a while to fill in, so only the
+    // single-line window is given up on.
+    let timeout = match args.view {
+        window::View::SingleLine => input_timeout(config),
+        _ => None,
+    };
+    match wait_for_input(&mut window, timeout, on_text, token) {
+        Ok(Waited::Input(text)) => {
+            window.detach();
+            Ok(text)
+        }
+        Ok(Waited::Cancelled) => {
+            window.kill()?;
+            Ok(None)
+        }
+        Ok(Waited::Exited) => {
+            // The window has closed its stdout, so it's exiting without telling us anything.
+            let (status, stderr) = window.wait()?;
+            anyhow::ensure!(
+                status.success(),
+                "the window exited with {status}: {}",
+                stderr.trim()
+            );
+            Ok(None)
+        }
+        Err(err) => {
+            window.kill()?;
+            Err(err)
+        }
+    }
+}
+
+/// An input window that is kept running in the background, so that it can be
+/// shown without waiting for a new process to start up.
+pub(super) struct PersistentWindow {
+    window: Option<WindowProcess>,
+}
+/// What happened when a [`PersistentWindow`] was asked for input.
+pub(super) enum Asked {
+    /// The user submitted the text, or `None` if they closed the window or the
+    /// job was cancelled.
+    Input(Option<String>),
+    /// The window couldn't take focus, so it was hidden again.
+    NotFocused,
+}
+impl PersistentWindow {
+    pub fn spawn(args: &window::Args) -> anyhow::Result<Self> {
+        Ok(Self {
+            window: Some(WindowProcess::spawn(&window::Args {
+                persistent: true,
+                ..args.clone()
+            })?),
+        })
+    }
+
+    /// Shows the window and waits for the user to submit something.
+    ///
+    /// Fails under the same conditions as [`run`], after which the window
+    /// shouldn't be used again.
+    pub fn ask(
+        &mut self,
+        config: &Config,
+        setup: &[HostMessage],
+        on_text: &dyn Fn(&str) -> Option<HostMessage>,
+        token: &CancellationToken,
+    ) -> anyhow::Result<Asked> {
+        let Some(window) = self.window.as_mut() else {
+            anyhow::bail!("the window has already exited");
+        };
+
+        // Anything left over is from the last time the window was shown.
+        window.messages().drain().for_each(drop);
+        send_all(window, setup)?;
+        window.send(HostMessage::Show)?;
+        if !wait_for_focus(window)? {
+            window.send(HostMessage::Hide)?;
+            return Ok(Asked::NotFocused);
+        }
+
+        match wait_for_input(window, input_timeout(config), on_text, token) {
+            Ok(Waited::Input(answer)) => Ok(Asked::Input(answer.map(Answer::into_text))),
+            Ok(Waited::Cancelled) => {
+                window.send(HostMessage::Hide)?;
+                Ok(Asked::Input(None))
+            }
+            Ok(Waited::Exited) => {
+                let (status, stderr) = self.window.take().unwrap().wait()?;
+                anyhow::bail!("the window exited with {status}: {}", stderr.trim())
+            }
+            Err(err) => {
+                window.send(HostMessage::Hide).ok();
+                Err(err)
+            }
+        }
+    }
+}
+impl Drop for PersistentWindow {
+    fn drop(&mut self) {
+        if let Some(mut window) = self.window.take() {
+            window.send(HostMessage::Close).ok();
+            window.detach();
+        }
+    }
+}
+
+fn send_all(window: &mut WindowProcess, messages: &[HostMessage]) -> anyhow::Result<()> {
+    for message in messages {
+        window.send(message.clone())?;
+    }
+    Ok(())
+}
+
+/// Waits for a newly-shown window to report that it has focus.
+fn wait_for_focus(window: &WindowProcess) -> anyhow::Result<bool> {
+    let deadline = Instant::now() + FOCUS_TIMEOUT;
+    loop {
+        match window.messages().recv_deadline(deadline) {
+            Ok(WindowMessage::Focused { focused }) => return Ok(focused),
+            Ok(WindowMessage::Error { message }) => {
+                anyhow::bail!("the window reported an error: {message}")
+            }
+            Ok(_) => {}
+            Err(flume::RecvTimeoutError::Timeout) => return Ok(false),
+            Err(flume::RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("the window exited while being shown")
+            }
+        }
+    }
+}
+
+enum Waited {
+    /// What the user submitted, or `None` if they closed the window.
+    Input(Option<Answer>),
+    /// The job was cancelled before the user was done.
+    Cancelled,
+    /// The window exited without saying anything.
+    Exited,
+}
+
+/// How long a single-line window can go without sending anything, including
+/// the [`WindowMessage::Alive`] it sends while idle, before it's assumed to have hung.
+fn input_timeout(config: &Config) -> Option<Duration> {
+    (config.window.timeout_secs > 0).then(|| Duration::from_secs(config.window.timeout_secs))
+}
+
+/// Waits for the user to be done with `window`, giving up if the job is
+/// cancelled or the window doesn't send anything for `timeout`.
+fn wait_for_input(
+    window: &mut WindowProcess,
+    timeout: Option<Duration>,
+    on_text: &dyn Fn(&str) -> Option<HostMessage>,
+    token: &CancellationToken,
+) -> anyhow::Result<Waited> {
+    let mut last_activity = Instant::now();
+    loop {
+        let message = window.messages().recv_timeout(Duration::from_millis(10));
+        if message.is_ok() {
+            last_activity = Instant::now();
+        }
+        match message {
+            Ok(WindowMessage::Submit { text }) => {
+                return Ok(Waited::Input(Some(Answer::Text(text))))
+            }
+            Ok(WindowMessage::Pick { command, text }) => {
+                return Ok(Waited::Input(Some(Answer::Pick { command, text })))
+            }
+            Ok(WindowMessage::Cancel) => return Ok(Waited::Input(None)),
+            Ok(WindowMessage::PartialText { text }) => {
+                if let Some(reply) = on_text(&text) {
+                    window.send(reply)?;
+                }
+            }
+            Ok(
+                WindowMessage::Focused { .. }
+                | WindowMessage::Alive
+                | WindowMessage::Copy { .. }
+                | WindowMessage::Regenerate
+                | WindowMessage::Edit { .. }
+                | WindowMessage::Export,
+            ) => {}
+            Ok(WindowMessage::Error { message }) => {
+                anyhow::bail!("the window reported an error: {message}")
+            }
+            Err(flume::RecvTimeoutError::Timeout) => {}
+            Err(flume::RecvTimeoutError::Disconnected) => return Ok(Waited::Exited),
+        }
+
+        if token.is_cancelled() {
+            return Ok(Waited::Cancelled);
+        }
+        if let Some(timeout) = timeout.filter(|&timeout| last_activity.elapsed() >= timeout) {
+            anyhow::bail!(
+                "the window didn't respond for {} seconds, so it was dismissed",
+                timeout.as_secs()
+            );
+        }
+    }
+}
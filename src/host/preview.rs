@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use crate::{
+    cancel::CancellationToken,
+    config::Config,
+    ipc::{HostMessage, WindowMessage, WindowProcess},
+    window,
+};
+
+/// What the user decided to do with the previewed text.
+pub(super) enum Decision {
+    /// Send the text, as edited by the user, to the command's outputs.
+    Accept(String),
+    /// Put the text on the clipboard instead.
+    Copy(String),
+    /// Throw the text away and generate it again.
+    Regenerate,
+    /// Throw the text away. This is also what happens when the user closes the
+    /// window or the job is cancelled.
+    Discard,
+}
+
+/// A window that shows the output as it's generated, and lets the user decide
+/// what to do with it.
+pub(super) struct Preview {
+    window: WindowProcess,
+}
+impl Preview {
+    pub fn spawn(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            window: WindowProcess::spawn(&window::Args::new(
+                config,
+                config.window.display_width,
+                config.window.display_height,
+                window::View::Preview,
+            ))?,
+        })
+    }
+
+    /// Clears the window for a new generation.
+    pub fn start(&mut self) -> anyhow::Result<()> {
+        self.window.send(HostMessage::Generating)
+    }
+
+    /// Adds a newly-generated token to the window.
+    ///
+    /// Returns what the user decided, if they didn't wait for generation to finish.
+    pub fn write(&mut self, token: &str) -> anyhow::Result<Option<Decision>> {
+        if let Some(decision) = self.poll()? {
+            return Ok(Some(decision));
+        }
+        // If this fails, the window has gone, which the next poll will notice.
+        self.window
+            .send(HostMessage::Output {
+                text: token.to_string(),
+            })
+            .ok();
+        Ok(None)
+    }
+
+    /// Tells the window that generation is done, then waits for the user to
+    /// decide what to do with the text.
+    ///
+    /// Unlike the input windows, this doesn't time out, as the user may be
+    /// reading or editing the text for a while.
+    pub fn finish(&mut self, token: &CancellationToken) -> anyhow::Result<Decision> {
+        self.window.send(HostMessage::Generated).ok();
+        loop {
+            if token.is_cancelled() {
+                return Ok(Decision::Discard);
+            }
+            if let Some(decision) = self.poll()? {
+                return Ok(decision);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Closes the window, waiting for it to go away so that focus has returned
+    /// to whatever the user was using before.
+    pub fn close(mut self) -> anyhow::Result<()> {
+        // The window closes itself once the user has decided, so this can fail.
+        self.window.send(HostMessage::Close).ok();
+        let (status, stderr) = self.window.wait()?;
+        anyhow::ensure!(
+            status.success(),
+            "the preview window exited with {status}: {}",
+            stderr.trim()
+        );
+        Ok(())
+    }
+
+    /// Checks whether the user has decided what to do yet.
+    fn poll(&self) -> anyhow::Result<Option<Decision>> {
+        loop {
+            match self.window.messages().try_recv() {
+                Ok(WindowMessage::Submit { text }) => return Ok(Some(Decision::Accept(text))),
+                Ok(WindowMessage::Copy { text }) => return Ok(Some(Decision::Copy(text))),
+                Ok(WindowMessage::Regenerate) => return Ok(Some(Decision::Regenerate)),
+                Ok(WindowMessage::Cancel) => return Ok(Some(Decision::Discard)),
+                Ok(WindowMessage::Error { message }) => {
+                    anyhow::bail!("the preview window reported an error: {message}")
+                }
+                Ok(
+                    WindowMessage::Pick { .. }
+                    | WindowMessage::PartialText { .. }
+                    | WindowMessage::Focused { .. }
+                    | WindowMessage::Alive
+                    | WindowMessage::Edit { .. }
+                    | WindowMessage::Export,
+                ) => {}
+                Err(flume::TryRecvError::Empty) => return Ok(None),
+                Err(flume::TryRecvError::Disconnected) => return Ok(Some(Decision::Discard)),
+            }
+        }
+    }
+}
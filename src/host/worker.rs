@@ -0,0 +1,994 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::Arc,
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::Context;
+use rand::SeedableRng;
+
+use super::{
+    popup::{self, Answer, Asked, PersistentWindow},
+    preview::{Decision, Preview},
+    Event, Job, KeyState,
+};
+use crate::{
+    backend::{Clipboard, EnigoKeyboard, Keyboard, SavedClipboard},
+    cancel::CancellationToken,
+    capture::CaptureKeys,
+    chat::{self, Chat, Role},
+    command::{
+        ChatCommand, ClipboardLoad, Command, CommandType, GenerateCommand, InputMethod, PromptMode,
+    },
+    config::{self, Config},
+    directive::{self, Directives},
+    history::History,
+    ipc::{HostMessage, WindowMessage, WindowProcess},
+    keycode::Keycode,
+    output::{self, Injected, Output},
+    window,
+};
+
+/// What the worker reports back to the host's core.
+pub(super) enum WorkerEvent {
+    /// The model has been loaded, and jobs can be run.
+    Ready,
+    /// Something the user may want to know about.
+    Status(String),
+    /// A token has been generated for the running job.
+    Token,
+    /// The running job has finished.
+    Finished(anyhow::Result<()>),
+    /// The worker couldn't start, and has stopped.
+    Failed(anyhow::Error),
+}
+
+/// Spawns the thread that owns the model and runs jobs, one at a time.
+///
+/// The keyboard and clipboard are created on this thread, as they aren't
+/// necessarily safe to send between threads.
+pub(super) fn spawn(
+    config: &'static Config,
+    keys: KeyState,
+    job_rx: flume::Receiver<Job>,
+    queued_notices: flume::Receiver<String>,
+    event_tx: flume::Sender<Event>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut worker = match Worker::new(config, keys, queued_notices, event_tx.clone()) {
+            Ok(worker) => worker,
+            Err(err) => {
+                event_tx.send(Event::Worker(WorkerEvent::Failed(err))).ok();
+                return;
+            }
+        };
+        worker.send(WorkerEvent::Ready);
+
+        while let Ok(job) = job_rx.recv() {
+            let result = worker.run(&job);
+            worker.send(WorkerEvent::Finished(result));
+        }
+    })
+}
+
+struct Worker {
+    config: &'static Config,
+    model: Arc<dyn llm::Model>,
+    /// The models from the config's `models` that have been used so far.
+    models: HashMap<String, Arc<dyn llm::Model>>,
+    keyboard: Box<dyn Keyboard>,
+    clipboard: Box<dyn Clipboard>,
+    keys: KeyState,
+    /// What the core says about jobs triggered while a chat is running, which
+    /// are shown in the chat window.
+    queued_notices: flume::Receiver<String>,
+    event_tx: flume::Sender<Event>,
+    /// The input window kept running between prompts, if enabled and working.
+    input_window: Option<PersistentWindow>,
+    last_generation: Option<LastGeneration>,
+}
+struct LastGeneration {
+    command: &'static GenerateCommand,
+    /// The prompt after it was put into the command's template.
+    prompt: String,
+    /// The directives it was run with, which are kept when regenerating.
+    directives: Directives,
+    /// What was injected into the focused application.
+    output: Injected,
+}
+impl Worker {
+    fn new(
+        config: &'static Config,
+        keys: KeyState,
+        queued_notices: flume::Receiver<String>,
+        event_tx: flume::Sender<Event>,
+    ) -> anyhow::Result<Self> {
+        let keyboard = Box::<EnigoKeyboard>::default();
+        let clipboard = Box::new(arboard::Clipboard::new()?);
+
+        let mut progress_window = if config.window.show_load_progress {
+            let args = window::Args::new(
+                config,
+                config.window.width,
+                config.window.height,
+                window::View::Progress(format!("Loading {}", config.model.path.display())),
+            );
+            WindowProcess::spawn(&args)
+                .map_err(|err| eprintln!("Couldn't show the loading window: {err:#}"))
+                .ok()
+        } else {
+            None
+        };
+        let model = load_model(&config.model, |progress| {
+            if let llm::LoadProgress::TensorLoaded {
+                current_tensor,
+                tensor_count,
+            } = &progress
+            {
+                if let Some(window) = &mut progress_window {
+                    let fraction = (*current_tensor + 1) as f32 / *tensor_count as f32;
+                    if window.send(HostMessage::LoadProgress { fraction }).is_err() {
+                        // The user closed it; there's no need to keep it up to date.
+                        progress_window = None;
+                    }
+                }
+            }
+            llm::load_progress_callback_stdout(progress)
+        });
+        if let Some(mut window) = progress_window {
+            window.send(HostMessage::Close).ok();
+            window.detach();
+        }
+        let model = model?.into();
+
+        let input_window = if config.window.persistent {
+            PersistentWindow::spawn(&singleline_args(config))
+                .map_err(|err| eprintln!("Couldn't start the persistent input window: {err:#}"))
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config,
+            model,
+            models: HashMap::new(),
+            keyboard,
+            clipboard,
+            keys,
+            queued_notices,
+            event_tx,
+            input_window,
+            last_generation: None,
+        })
+    }
+
+    fn send(&self, event: WorkerEvent) {
+        // If the core has gone away, there's nobody left to tell.
+        self.event_tx.send(Event::Worker(event)).ok();
+    }
+
+    fn status(&self, message: impl Into<String>) {
+        self.send(WorkerEvent::Status(message.into()));
+    }
+
+    fn run(&mut self, job: &Job) -> anyhow::Result<()> {
+        let command: &'static Command = job.command;
+        self.keys.start_output();
+        let result = match &command.ty {
+            CommandType::Generate(generate) => self.generate(command, generate, &job.token),
+            CommandType::UndoLast => {
+                self.undo_last();
+                Ok(())
+            }
+            CommandType::Regenerate => self.regenerate(&job.token),
+            CommandType::Palette => self.palette(&job.token),
+            CommandType::Chat(chat) => self.chat(chat, &job.token),
+            CommandType::Cancel => Ok(()),
+        };
+        self.keys.finish_output();
+
+        result
+    }
+
+    fn generate(
+        &mut self,
+        trigger: &'static Command,
+        command: &'static GenerateCommand,
+        token: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        // Only prompts typed into a window are worth recalling.
+        let typed = matches!(
+            command.input,
+            InputMethod::SingleLineUi | InputMethod::MultiLineUi
+        );
+        let history = typed.then(|| load_history(trigger, command)).flatten();
+        let counter = TokenCounter {
+            config: self.config,
+            command,
+            model: self.model.clone(),
+            models: self.models.clone(),
+        };
+        let mut setup = window_setup(self.config, history.as_ref());
+        // Show how much the template uses before anything's been typed.
+        setup.extend(counter.usage(""));
+        let on_text = |text: &str| counter.usage(text);
+
+        let prompt = match &command.input {
+            InputMethod::SingleLineUi => self.ask_for_singleline_input(&setup, &on_text, token)?,
+            InputMethod::MultiLineUi => {
+                ask_for_multiline_input(self.config, &setup, &on_text, token)?
+            }
+            InputMethod::Clipboard(clipboard_input) => {
+                self.keys.wait_for_release();
+                if token.is_cancelled() {
+                    return Ok(());
+                }
+                read_clipboard(
+                    &self.config.general.capture_keys,
+                    self.keyboard.as_mut(),
+                    self.clipboard.as_mut(),
+                    clipboard_input.load,
+                    token,
+                )?
+            }
+        };
+
+        self.run_prompt(command, history, prompt, typed, token)
+    }
+
+    /// Lets the user pick a named command and enter a prompt for it, then runs it.
+    fn palette(&mut self, token: &CancellationToken) -> anyhow::Result<()> {
+        let commands: Vec<_> = self
+            .config
+            .commands
+            .iter()
+            .filter_map(|command| match &command.ty {
+                CommandType::Generate(generate) if command.name.is_some() => {
+                    Some((command, generate))
+                }
+                _ => None,
+            })
+            .collect();
+        if commands.is_empty() {
+            self.status("There are no named commands to show in the palette");
+            return Ok(());
+        }
+
+        let args = window::Args::new(
+            self.config,
+            self.config.window.display_width,
+            self.config.window.display_height,
+            window::View::Palette(
+                commands
+                    .iter()
+                    .map(|(command, _)| window::PaletteEntry {
+                        name: command.label(),
+                        description: command.description.clone(),
+                    })
+                    .collect(),
+            ),
+        );
+        let answer = popup::run(self.config, &args, &[], &|_| None, token)
+            .context("the palette window failed")?;
+        let Some(Answer::Pick { command, text }) = answer else {
+            return Ok(());
+        };
+        let (trigger, command) = commands
+            .get(command)
+            .copied()
+            .context("the palette picked a command that doesn't exist")?;
+
+        self.run_prompt(command, load_history(trigger, command), text, true, token)
+    }
+
+    /// Holds a conversation in the chat window until the user closes it or the
+    /// job is cancelled. Other jobs wait until then.
+    fn chat(
+        &mut self,
+        command: &'static ChatCommand,
+        token: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        if let Some(name) = &command.model {
+            self.load_named_model(name)?;
+        }
+        let mut window = WindowProcess::spawn(&window::Args::new(
+            self.config,
+            self.config.window.chat_width,
+            self.config.window.chat_height,
+            window::View::Chat,
+        ))?;
+        // Don't leave the window open with nothing listening to it, however the chat ends.
+        let result = self.converse(command, &mut window, token);
+        let killed = window.kill();
+        result.and(killed)
+    }
+
+    /// Answers the messages from the chat `window` until it's closed.
+    fn converse(
+        &mut self,
+        command: &'static ChatCommand,
+        window: &mut WindowProcess,
+        token: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let mut chat = Chat::new()?;
+        // The reply is cut off if the model starts writing the user's next message.
+        let stop = format!("\n{}", command.user.trim());
+        // Messages that arrived while a reply was being written.
+        let mut pending = VecDeque::new();
+        // Notices about jobs queued before this chat started are stale.
+        self.queued_notices.drain().for_each(drop);
+
+        loop {
+            for text in self.queued_notices.drain() {
+                window.send(HostMessage::Status { text })?;
+            }
+            let message = match pending.pop_front() {
+                Some(message) => message,
+                None => match window.messages().recv_timeout(Duration::from_millis(10)) {
+                    Ok(message) => message,
+                    Err(flume::RecvTimeoutError::Timeout) if !token.is_cancelled() => continue,
+                    Err(_) => return Ok(()),
+                },
+            };
+            match message {
+                WindowMessage::Submit { text } if !text.trim().is_empty() => {
+                    chat.messages.push(chat::Message {
+                        role: Role::User,
+                        text,
+                    })
+                }
+                WindowMessage::Edit { index, text } => chat.edit(index, text)?,
+                WindowMessage::Regenerate => {
+                    if chat
+                        .messages
+                        .last()
+                        .is_some_and(|message| message.role == Role::Assistant)
+                    {
+                        chat.messages.pop();
+                    }
+                    if chat.messages.is_empty() {
+                        continue;
+                    }
+                }
+                WindowMessage::Export => {
+                    let text = match chat.export() {
+                        Ok(path) => format!("Exported to {}", path.display()),
+                        Err(err) => format!("Couldn't export the chat: {err:#}"),
+                    };
+                    window.send(HostMessage::Status { text })?;
+                    continue;
+                }
+                WindowMessage::Cancel => return Ok(()),
+                WindowMessage::Error { message } => {
+                    anyhow::bail!("the chat window reported an error: {message}")
+                }
+                _ => continue,
+            }
+
+            window.send(HostMessage::Chat {
+                messages: chat.messages.clone(),
+            })?;
+            window.send(HostMessage::Generating)?;
+            let model = match &command.model {
+                Some(name) => self.models[name].as_ref(),
+                None => self.model.as_ref(),
+            };
+            let mut reply = String::new();
+            // Set if the window was closed while the reply was being written.
+            let mut closed = false;
+            run_model(
+                model,
+                &chat.prompt(command),
+                &Directives::default(),
+                rand::random(),
+                token,
+                &self.event_tx,
+                |tok| {
+                    // The global cancel hotkey is ignored while the window has
+                    // focus, so closing the window is how the user stops a reply.
+                    for message in window.messages().try_iter() {
+                        match message {
+                            WindowMessage::Cancel => closed = true,
+                            message => pending.push_back(message),
+                        }
+                    }
+                    if closed || window.messages().is_disconnected() {
+                        closed = true;
+                        return Ok(true);
+                    }
+
+                    reply.push_str(tok);
+                    window
+                        .send(HostMessage::Output {
+                            text: tok.to_string(),
+                        })
+                        .ok();
+                    match reply.find(&stop) {
+                        Some(index) => {
+                            reply.truncate(index);
+                            Ok(true)
+                        }
+                        None => Ok(false),
+                    }
+                },
+            )?;
+            if closed {
+                return Ok(());
+            }
+
+            chat.messages.push(chat::Message {
+                role: Role::Assistant,
+                text: reply.trim().to_string(),
+            });
+            if let Err(err) = chat.save() {
+                self.status(format!("Couldn't save the chat: {err:#}"));
+            }
+            // Send the whole chat again, in case the end of the reply was cut off.
+            window
+                .send(HostMessage::Chat {
+                    messages: chat.messages.clone(),
+                })
+                .ok();
+            window.send(HostMessage::Generated).ok();
+        }
+    }
+
+    /// Loads the model called `name` from the config's `models`, unless it's
+    /// already been loaded.
+    fn load_named_model(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.models.contains_key(name) {
+            return Ok(());
+        }
+        let model = self
+            .config
+            .models
+            .get(name)
+            .with_context(|| format!("there's no model called {name:?} in the config"))?;
+
+        self.status(format!("Loading model {name}"));
+        let model = load_model(model, llm::load_progress_callback_stdout)
+            .with_context(|| format!("couldn't load model {name:?}"))?;
+        self.models.insert(name.to_string(), model.into());
+        Ok(())
+    }
+
+    /// Remembers `prompt` in `history`, then puts it into the command's template
+    /// and runs the model on it.
+    ///
+    /// If the prompt was `typed` into a window, any directives at its start are
+    /// applied.
+    fn run_prompt(
+        &mut self,
+        command: &'static GenerateCommand,
+        history: Option<History>,
+        prompt: String,
+        typed: bool,
+        token: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        if prompt.is_empty() || token.is_cancelled() {
+            return Ok(());
+        }
+        if let Some(mut history) = history {
+            if let Err(err) = history.add(&prompt) {
+                eprintln!("Couldn't save the prompt to the history: {err:#}");
+            }
+        }
+
+        let (directives, prompt) = if typed {
+            directive::parse(&prompt)?
+        } else {
+            (Directives::default(), prompt.as_str())
+        };
+        let command = match &directives.command {
+            Some(name) => find_command(self.config, name)?,
+            None => command,
+        };
+
+        if prompt.is_empty() {
+            return Ok(());
+        }
+
+        let new_prompt = fill_template(command, prompt);
+        self.infer(command, new_prompt, directives, rand::random(), token)
+    }
+
+    /// Runs the model on `prompt`, sending the results to the command's outputs.
+    ///
+    /// If the command has a preview, the results are shown there first, and
+    /// only sent to the outputs if the user accepts them.
+    fn infer(
+        &mut self,
+        command: &'static GenerateCommand,
+        prompt: String,
+        directives: Directives,
+        seed: u64,
+        token: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        // If anything goes wrong from here on, we don't know what's been output.
+        self.last_generation = None;
+
+        if let Some(name) = &directives.model {
+            self.load_named_model(name)?;
+        }
+        let model = match &directives.model {
+            Some(name) => self.models[name].as_ref(),
+            None => self.model.as_ref(),
+        };
+
+        let output = if command.preview {
+            let mut preview = Preview::spawn(self.config)?;
+            let mut seed = seed;
+            let decision = loop {
+                preview.start()?;
+                let mut decision = None;
+                run_model(
+                    model,
+                    &prompt,
+                    &directives,
+                    seed,
+                    token,
+                    &self.event_tx,
+                    |tok| {
+                        let (tok, stop) = output::cut_at_newline(&command.newline, tok);
+                        decision = preview.write(tok)?;
+                        Ok(stop || decision.is_some())
+                    },
+                )?;
+                let decision = match decision {
+                    Some(decision) => decision,
+                    None => preview.finish(token)?,
+                };
+                match decision {
+                    Decision::Regenerate => seed = rand::random(),
+                    decision => break decision,
+                }
+            };
+            preview.close()?;
+
+            match decision {
+                Decision::Accept(text) => {
+                    // The keys used to accept may still be held, and would
+                    // change what's typed.
+                    self.keys.wait_for_release();
+                    let mut output = Output::new(
+                        self.config,
+                        &command.output,
+                        &command.newline,
+                        self.keyboard.as_mut(),
+                        self.clipboard.as_mut(),
+                    )?;
+                    output.write(&text)?;
+                    output.finish()?
+                }
+                Decision::Copy(text) => {
+                    self.clipboard.set_text(&text)?;
+                    return Ok(());
+                }
+                Decision::Regenerate | Decision::Discard => return Ok(()),
+            }
+        } else {
+            let mut output = Output::new(
+                self.config,
+                &command.output,
+                &command.newline,
+                self.keyboard.as_mut(),
+                self.clipboard.as_mut(),
+            )?;
+            run_model(
+                model,
+                &prompt,
+                &directives,
+                seed,
+                token,
+                &self.event_tx,
+                |tok| output.write(tok),
+            )?;
+            output.finish()?
+        };
+
+        if output.backspaces() > 0 {
+            self.keys.output_injected();
+        }
+        self.last_generation = Some(LastGeneration {
+            command,
+            prompt,
+            directives,
+            output,
+        });
+
+        Ok(())
+    }
+
+    /// Removes the text inserted by the last generation, as long as the user
+    /// hasn't typed anything since.
+    fn undo_last(&mut self) {
+        if !self.erase_last_output() {
+            return;
+        }
+        if let Some(last_generation) = &mut self.last_generation {
+            last_generation.output = Injected::default();
+        }
+    }
+
+    /// Erases the output of the last generation and runs its prompt again with a new seed.
+    fn regenerate(&mut self, token: &CancellationToken) -> anyhow::Result<()> {
+        if !self.erase_last_output() {
+            return Ok(());
+        }
+        let Some(LastGeneration {
+            command,
+            prompt,
+            directives,
+            ..
+        }) = self.last_generation.take()
+        else {
+            return Ok(());
+        };
+
+        let seed = rand::random();
+        self.status(format!("Regenerating with seed {seed}"));
+        self.infer(command, prompt, directives, seed, token)
+    }
+
+    /// Opens the input window and waits for the user to enter a prompt.
+    ///
+    /// Returns an empty string if the user closed the window, or if the job was
+    /// cancelled, in which case the window is closed.
+    fn ask_for_singleline_input(
+        &mut self,
+        setup: &[HostMessage],
+        on_text: &dyn Fn(&str) -> Option<HostMessage>,
+        token: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        if let Some(window) = &mut self.input_window {
+            match window.ask(self.config, setup, on_text, token) {
+                Ok(Asked::Input(prompt)) => return Ok(prompt.unwrap_or_default()),
+                Ok(Asked::NotFocused) => {
+                    self.status("The input window couldn't take focus; starting a new one for each prompt instead");
+                    self.input_window = None;
+                }
+                Err(err) => {
+                    self.input_window = None;
+                    return Err(err.context("the input window failed"));
+                }
+            }
+        }
+
+        let prompt = popup::run(
+            self.config,
+            &singleline_args(self.config),
+            setup,
+            on_text,
+            token,
+        )
+        .context("the input window failed")?;
+        Ok(prompt.map(Answer::into_text).unwrap_or_default())
+    }
+
+    /// Sends backspaces to remove the text inserted by the last generation.
+    ///
+    /// Returns `false` if this wasn't possible.
+    fn erase_last_output(&mut self) -> bool {
+        let Some(backspaces) = self
+            .last_generation
+            .as_ref()
+            .map(|last_generation| last_generation.output.backspaces())
+        else {
+            self.status("Nothing has been generated yet");
+            return false;
+        };
+        if self.keys.typed_since_output() {
+            self.status(
+                "Keys have been pressed since the last generation; not touching its output",
+            );
+            return false;
+        }
+
+        self.keys.wait_for_release();
+        for _ in 0..backspaces {
+            self.keyboard.key_click(Keycode::Backspace);
+        }
+        true
+    }
+}
+
+/// Loads the prompt from the clipboard, first copying the text described by `load`
+/// into it. The user's clipboard is restored afterwards.
+fn read_clipboard(
+    capture_keys: &CaptureKeys,
+    keyboard: &mut dyn Keyboard,
+    clipboard: &mut dyn Clipboard,
+    load: Option<ClipboardLoad>,
+    token: &CancellationToken,
+) -> anyhow::Result<String> {
+    let saved = load.map(|_| SavedClipboard::save(clipboard));
+
+    if let Some(load) = load {
+        capture_keys
+            .copy(keyboard, load)
+            .context("couldn't copy the text for the prompt")?;
+    }
+
+    // Even if we've been cancelled or couldn't read the clipboard, the user's
+    // clipboard needs to be put back.
+    let text = if token.is_cancelled() {
+        Ok(String::new())
+    } else {
+        clipboard
+            .get_text()
+            .context("couldn't read what was copied from the clipboard")
+    };
+    if let Some(saved) = saved {
+        saved.restore(clipboard)?;
+    }
+    text
+}
+
+/// Runs `model` on `prompt` with the settings from `directives`, passing each
+/// token to `on_token` until it returns `true`, it fails, or the job is cancelled.
+fn run_model(
+    model: &dyn llm::Model,
+    prompt: &str,
+    directives: &Directives,
+    seed: u64,
+    token: &CancellationToken,
+    event_tx: &flume::Sender<Event>,
+    mut on_token: impl FnMut(&str) -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    let mut sampler = llm::samplers::TopPTopK::default();
+    if let Some(temperature) = directives.temperature {
+        sampler.temperature = temperature;
+    }
+    let parameters = llm::InferenceParameters {
+        sampler: Arc::new(sampler),
+        ..Default::default()
+    };
+
+    let mut token_error = None;
+    model.start_session(Default::default()).infer(
+        model,
+        &mut rand::rngs::StdRng::seed_from_u64(seed),
+        &llm::InferenceRequest {
+            prompt: prompt.into(),
+            parameters: &parameters,
+            play_back_previous_tokens: false,
+            maximum_token_count: directives.max_tokens,
+        },
+        &mut Default::default(),
+        |tok| {
+            if token.is_cancelled() {
+                return Ok(llm::InferenceFeedback::Halt);
+            }
+
+            let mut feedback = llm::InferenceFeedback::Continue;
+            if let llm::InferenceResponse::InferredToken(t) = tok {
+                event_tx.send(Event::Worker(WorkerEvent::Token)).ok();
+                match on_token(&t) {
+                    Ok(false) => {}
+                    Ok(true) => feedback = llm::InferenceFeedback::Halt,
+                    Err(err) => {
+                        token_error = Some(err);
+                        feedback = llm::InferenceFeedback::Halt;
+                    }
+                }
+            }
+            Ok::<_, Infallible>(feedback)
+        },
+    )?;
+    match token_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+fn load_model(
+    model: &config::Model,
+    progress_callback: impl FnMut(llm::LoadProgress),
+) -> anyhow::Result<Box<dyn llm::Model>> {
+    Ok(llm::load_dynamic(
+        Some(model.architecture()?),
+        // TODO: support others
+        &model.path,
+        llm::TokenizerSource::Embedded,
+        llm::ModelParameters {
+            prefer_mmap: model.prefer_mmap,
+            context_size: model.context_token_length,
+            use_gpu: model.use_gpu,
+            ..Default::default()
+        },
+        progress_callback,
+    )?)
+}
+
+/// Puts `prompt` into the command's template.
+fn fill_template(command: &GenerateCommand, prompt: &str) -> String {
+    match &command.mode {
+        PromptMode::Autocomplete => prompt.to_string(),
+        PromptMode::Prompt(template) => template.replace("{{PROMPT}}", prompt),
+    }
+}
+
+/// Works out how much of the context a prompt being typed for a command would use.
+struct TokenCounter {
+    config: &'static Config,
+    command: &'static GenerateCommand,
+    model: Arc<dyn llm::Model>,
+    /// The named models that have been loaded, for prompts that use `/model`.
+    /// Models that haven't been loaded yet are counted with the main model.
+    models: HashMap<String, Arc<dyn llm::Model>>,
+}
+impl TokenCounter {
+    /// Counts the tokens in `text` once it's been put into the template,
+    /// following any directives it starts with.
+    fn usage(&self, text: &str) -> Option<HostMessage> {
+        // The directives may be half-typed, in which case they're counted as text.
+        let (directives, prompt) =
+            directive::parse(text).unwrap_or_else(|_| (Directives::default(), text));
+        let command = directives
+            .command
+            .and_then(|name| find_command(self.config, &name).ok())
+            .unwrap_or(self.command);
+        let model = directives
+            .model
+            .and_then(|name| self.models.get(&name))
+            .unwrap_or(&self.model);
+
+        let tokens = model
+            .tokenizer()
+            .tokenize(&fill_template(command, prompt), true)
+            .map_err(|err| eprintln!("Couldn't count the prompt's tokens: {err}"))
+            .ok()?;
+        Some(HostMessage::TokenUsage {
+            used: tokens.len(),
+            limit: model.context_size(),
+        })
+    }
+}
+
+/// The messages that set up an input window: the prompt history, and the
+/// names that directives can be completed with.
+fn window_setup(config: &Config, history: Option<&History>) -> Vec<HostMessage> {
+    vec![
+        HostMessage::History {
+            entries: history.map(|h| h.entries().to_vec()).unwrap_or_default(),
+        },
+        HostMessage::Completions {
+            models: config.models.keys().cloned().collect(),
+            commands: config
+                .commands
+                .iter()
+                .filter(|command| matches!(command.ty, CommandType::Generate(_)))
+                .filter_map(|command| command.name.clone())
+                .collect(),
+        },
+    ]
+}
+
+/// Finds the generate command called `name`, for `/cmd`.
+fn find_command(config: &'static Config, name: &str) -> anyhow::Result<&'static GenerateCommand> {
+    config
+        .commands
+        .iter()
+        .find_map(|command| match &command.ty {
+            CommandType::Generate(generate) if command.name.as_deref() == Some(name) => {
+                Some(generate)
+            }
+            _ => None,
+        })
+        .with_context(|| format!("there's no generate command called {name:?}"))
+}
+
+/// Loads the prompt history for `trigger`, unless `command` has it turned off.
+fn load_history(trigger: &Command, command: &GenerateCommand) -> Option<History> {
+    if !command.history {
+        return None;
+    }
+    History::load(&trigger.label())
+        .map_err(|err| eprintln!("Couldn't load the prompt history: {err:#}"))
+        .ok()
+}
+
+/// Opens the multi-line editor and waits for the user to enter a prompt, like
+/// [`Worker::ask_for_singleline_input`].
+fn ask_for_multiline_input(
+    config: &Config,
+    setup: &[HostMessage],
+    on_text: &dyn Fn(&str) -> Option<HostMessage>,
+    token: &CancellationToken,
+) -> anyhow::Result<String> {
+    let args = window::Args::new(
+        config,
+        config.window.multiline_width,
+        config.window.multiline_height,
+        window::View::MultiLine(window::SizeLimits {
+            min_width: config.window.multiline_min_width,
+            min_height: config.window.multiline_min_height,
+            max_width: config.window.multiline_max_width,
+            max_height: config.window.multiline_max_height,
+        }),
+    );
+    let prompt =
+        popup::run(config, &args, setup, on_text, token).context("the editor window failed")?;
+    Ok(prompt.map(Answer::into_text).unwrap_or_default())
+}
+
+fn singleline_args(config: &Config) -> window::Args {
+    window::Args::new(
+        config,
+        config.window.width,
+        config.window.height,
+        window::View::SingleLine,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fakes::MemoryClipboard;
+
+    /// A keyboard that puts `copied` on the clipboard when C is pressed, like
+    /// the focused application would for the copy shortcut.
+    struct CopyingKeyboard {
+        clipboard: MemoryClipboard,
+        copied: &'static str,
+    }
+    impl Keyboard for CopyingKeyboard {
+        fn key_down(&mut self, _key: Keycode) {}
+
+        fn key_up(&mut self, _key: Keycode) {}
+
+        fn key_click(&mut self, key: Keycode) {
+            if key == Keycode::C {
+                self.clipboard.set_text(self.copied).unwrap();
+            }
+        }
+
+        fn type_text(&mut self, _text: &str) {}
+    }
+
+    fn read_line(clipboard: &MemoryClipboard, token: &CancellationToken) -> anyhow::Result<String> {
+        let mut keyboard = CopyingKeyboard {
+            clipboard: clipboard.clone(),
+            copied: "the line",
+        };
+        let mut capture_keys = CaptureKeys::default();
+        capture_keys.add_defaults().unwrap();
+        read_clipboard(
+            &capture_keys,
+            &mut keyboard,
+            &mut clipboard.clone(),
+            Some(ClipboardLoad::Line),
+            token,
+        )
+    }
+
+    #[test]
+    fn read_clipboard_restores_the_clipboard() {
+        let clipboard = MemoryClipboard::with_text("old");
+        let text = read_line(&clipboard, &CancellationToken::default()).unwrap();
+        assert_eq!(text, "the line");
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn read_clipboard_restores_the_clipboard_when_cancelled() {
+        let clipboard = MemoryClipboard::with_text("old");
+        let token = CancellationToken::default();
+        token.cancel();
+        let text = read_line(&clipboard, &token).unwrap();
+        assert_eq!(text, "");
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn read_clipboard_restores_the_clipboard_when_reading_fails() {
+        let clipboard = MemoryClipboard::with_text("old");
+        // Saving the clipboard works, but reading what was copied doesn't.
+        clipboard.fail_reads_after(1);
+        assert!(read_line(&clipboard, &CancellationToken::default()).is_err());
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+}
@@ -0,0 +1,277 @@
+//! The protocol spoken between the host and its window processes.
+//!
+//! Each message is one line of JSON, tagged with the protocol version. The host
+//! writes [`HostMessage`]s to the window's stdin, and the window writes
+//! [`WindowMessage`]s to its stdout. Lines that aren't messages are skipped, so
+//! a stray print can't be mistaken for user input.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    process::{self, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::JoinHandle,
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{chat, window};
+
+pub const VERSION: u32 = 1;
+
+/// Messages sent from a window to the host.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WindowMessage {
+    /// The user submitted their input.
+    Submit { text: String },
+    /// The user picked a command from the palette, and entered `text` for it.
+    /// `command` is the index of the command in the list the window was given.
+    Pick { command: usize, text: String },
+    /// The user closed the window without submitting anything.
+    Cancel,
+    /// The user wants the previewed text put on the clipboard instead of being output.
+    Copy { text: String },
+    /// The user wants the previewed text, or the last reply in a chat, thrown
+    /// away and generated again.
+    Regenerate,
+    /// The user changed their message at `index` in the chat, and wants the
+    /// conversation to carry on from there.
+    Edit { index: usize, text: String },
+    /// The user wants the chat saved as Markdown.
+    Export,
+    /// The user's input has changed, but hasn't been submitted yet.
+    PartialText { text: String },
+    /// The window has gained or lost focus.
+    Focused { focused: bool },
+    /// Sent every so often, to show that the window hasn't hung while the user
+    /// is making up their mind.
+    Alive,
+    /// The window ran into a problem it couldn't recover from.
+    Error { message: String },
+}
+
+/// Messages sent from the host to a window.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum HostMessage {
+    /// A short description of what the host is doing.
+    Status { text: String },
+    /// Text generated by the model, to be appended to what's being shown.
+    Output { text: String },
+    /// The host has started generating, replacing any output shown so far.
+    Generating,
+    /// The host has finished generating.
+    Generated,
+    /// Every message in the chat so far, replacing what's being shown.
+    Chat { messages: Vec<chat::Message> },
+    /// How much of the model has been loaded, from 0 to 1.
+    LoadProgress { fraction: f32 },
+    /// Prompts previously entered for the current command, oldest first.
+    History { entries: Vec<String> },
+    /// The names that the input window can complete after `/model` and `/cmd`.
+    Completions {
+        models: Vec<String>,
+        commands: Vec<String>,
+    },
+    /// How many tokens the prompt being typed uses, once it's been put into
+    /// the command's template, out of the model's context length.
+    TokenUsage { used: usize, limit: usize },
+    /// Show a persistent window, and try to give it focus.
+    Show,
+    /// Hide a persistent window until it's needed again.
+    Hide,
+    /// The window is no longer needed.
+    Close,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    #[serde(flatten)]
+    message: T,
+}
+
+/// Writes `message` to `writer` as a single line.
+pub fn write<T: Serialize>(mut writer: impl Write, message: T) -> anyhow::Result<()> {
+    let line = serde_json::to_string(&Envelope {
+        version: VERSION,
+        message,
+    })?;
+    writeln!(writer, "{line}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Parses a line written by [`write`].
+pub fn parse<T: DeserializeOwned>(line: &str) -> anyhow::Result<T> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: u32,
+    }
+
+    let Version { version } = serde_json::from_str(line).context("not a message")?;
+    anyhow::ensure!(
+        version == VERSION,
+        "unsupported protocol version {version}, expected {VERSION}"
+    );
+    Ok(serde_json::from_str::<Envelope<T>>(line)?.message)
+}
+
+/// Reads messages from `reader` on a new thread, passing each to `on_message`
+/// until `reader` closes or `on_message` returns `false`.
+pub fn read_in_background<T: DeserializeOwned>(
+    reader: impl Read + Send + 'static,
+    mut on_message: impl FnMut(T) -> bool + Send + 'static,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            match parse(&line) {
+                Ok(message) => {
+                    if !on_message(message) {
+                        return;
+                    }
+                }
+                Err(err) => eprintln!("Skipping {line:?}: {err:#}"),
+            }
+        }
+    })
+}
+
+/// How many windows have focus, going by their [`WindowMessage::Focused`] messages.
+static FOCUSED_WINDOWS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether one of alpa's windows has focus, in which case the keys the user
+/// presses are meant for it.
+pub fn window_has_focus() -> bool {
+    FOCUSED_WINDOWS.load(Ordering::SeqCst) > 0
+}
+
+/// Whether a window has focus, kept in [`FOCUSED_WINDOWS`]. A window that
+/// exits stops counting, even if it didn't say it lost focus.
+#[derive(Default)]
+struct FocusTracker(bool);
+impl FocusTracker {
+    fn set(&mut self, focused: bool) {
+        if focused != self.0 {
+            if focused {
+                FOCUSED_WINDOWS.fetch_add(1, Ordering::SeqCst);
+            } else {
+                FOCUSED_WINDOWS.fetch_sub(1, Ordering::SeqCst);
+            }
+            self.0 = focused;
+        }
+    }
+}
+impl Drop for FocusTracker {
+    fn drop(&mut self) {
+        self.set(false);
+    }
+}
+
+/// A running window process, and the means to talk to it.
+pub struct WindowProcess {
+    child: process::Child,
+    stdin: process::ChildStdin,
+    messages: flume::Receiver<WindowMessage>,
+    stderr: JoinHandle<String>,
+}
+impl WindowProcess {
+    pub fn spawn(args: &window::Args) -> anyhow::Result<Self> {
+        let mut child = process::Command::new(env::current_exe()?)
+            .arg(serde_json::to_string(args)?)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()
+            .context("couldn't start the window process")?;
+
+        let stdin = child.stdin.take().context("window has no stdin")?;
+        let stdout = child.stdout.take().context("window has no stdout")?;
+        let mut stderr = child.stderr.take().context("window has no stderr")?;
+
+        let (message_tx, messages) = flume::unbounded();
+        let mut focus = FocusTracker::default();
+        read_in_background(stdout, move |message| {
+            if let WindowMessage::Focused { focused } = message {
+                focus.set(focused);
+            }
+            message_tx.send(message).is_ok()
+        });
+        // Read stderr as we go, so that a chatty window can't fill the pipe and block.
+        let stderr = std::thread::spawn(move || {
+            let mut output = String::new();
+            stderr.read_to_string(&mut output).ok();
+            output
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            messages,
+            stderr,
+        })
+    }
+
+    pub fn send(&mut self, message: HostMessage) -> anyhow::Result<()> {
+        write(&mut self.stdin, message).context("couldn't send a message to the window")
+    }
+
+    /// Messages from the window. This disconnects once the window has exited.
+    pub fn messages(&self) -> &flume::Receiver<WindowMessage> {
+        &self.messages
+    }
+
+    /// Closes the window, whether it wants to or not.
+    pub fn kill(mut self) -> anyhow::Result<()> {
+        // This fails if the window has already exited, which is fine.
+        self.child.kill().ok();
+        self.child.wait()?;
+        Ok(())
+    }
+
+    /// Waits for the window to exit, and returns what it wrote to stderr.
+    pub fn wait(mut self) -> anyhow::Result<(ExitStatus, String)> {
+        let status = self.child.wait()?;
+        let stderr = self.stderr.join().unwrap_or_default();
+        Ok((status, stderr))
+    }
+
+    /// Leaves the window to run by itself. It will be cleaned up once it exits.
+    pub fn detach(self) {
+        let Self { mut child, .. } = self;
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_what_write_wrote() {
+        let mut line = vec![];
+        let message = WindowMessage::Submit {
+            text: "hello".to_string(),
+        };
+        write(&mut line, message.clone()).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        assert_eq!(parse::<WindowMessage>(line.trim_end()).unwrap(), message);
+    }
+
+    #[test]
+    fn parse_rejects_other_versions() {
+        let err = parse::<WindowMessage>(r#"{"version":0,"type":"cancel"}"#).unwrap_err();
+        assert!(err.to_string().contains("unsupported protocol version"));
+    }
+
+    #[test]
+    fn parse_rejects_lines_that_arent_messages() {
+        assert!(parse::<WindowMessage>("Loading fonts...").is_err());
+        assert!(parse::<WindowMessage>(r#"{"type":"cancel"}"#).is_err());
+    }
+}
@@ -327,3 +327,105 @@ impl From<device_query::Keycode> for Keycode {
         }
     }
 }
+impl Keycode {
+    /// Converts this keycode to the equivalent [`enigo::Key`], if there is one.
+    ///
+    /// Keys that produce characters are sent as layout keys, so the result
+    /// depends on the active keyboard layout.
+    pub fn to_enigo(self) -> Option<enigo::Key> {
+        use enigo::Key as EK;
+
+        let layout = |c| Some(EK::Layout(c));
+        match self {
+            Keycode::Key0 | Keycode::Numpad0 => layout('0'),
+            Keycode::Key1 | Keycode::Numpad1 => layout('1'),
+            Keycode::Key2 | Keycode::Numpad2 => layout('2'),
+            Keycode::Key3 | Keycode::Numpad3 => layout('3'),
+            Keycode::Key4 | Keycode::Numpad4 => layout('4'),
+            Keycode::Key5 | Keycode::Numpad5 => layout('5'),
+            Keycode::Key6 | Keycode::Numpad6 => layout('6'),
+            Keycode::Key7 | Keycode::Numpad7 => layout('7'),
+            Keycode::Key8 | Keycode::Numpad8 => layout('8'),
+            Keycode::Key9 | Keycode::Numpad9 => layout('9'),
+            Keycode::A => layout('a'),
+            Keycode::B => layout('b'),
+            Keycode::C => layout('c'),
+            Keycode::D => layout('d'),
+            Keycode::E => layout('e'),
+            Keycode::F => layout('f'),
+            Keycode::G => layout('g'),
+            Keycode::H => layout('h'),
+            Keycode::I => layout('i'),
+            Keycode::J => layout('j'),
+            Keycode::K => layout('k'),
+            Keycode::L => layout('l'),
+            Keycode::M => layout('m'),
+            Keycode::N => layout('n'),
+            Keycode::O => layout('o'),
+            Keycode::P => layout('p'),
+            Keycode::Q => layout('q'),
+            Keycode::R => layout('r'),
+            Keycode::S => layout('s'),
+            Keycode::T => layout('t'),
+            Keycode::U => layout('u'),
+            Keycode::V => layout('v'),
+            Keycode::W => layout('w'),
+            Keycode::X => layout('x'),
+            Keycode::Y => layout('y'),
+            Keycode::Z => layout('z'),
+            Keycode::F1 => Some(EK::F1),
+            Keycode::F2 => Some(EK::F2),
+            Keycode::F3 => Some(EK::F3),
+            Keycode::F4 => Some(EK::F4),
+            Keycode::F5 => Some(EK::F5),
+            Keycode::F6 => Some(EK::F6),
+            Keycode::F7 => Some(EK::F7),
+            Keycode::F8 => Some(EK::F8),
+            Keycode::F9 => Some(EK::F9),
+            Keycode::F10 => Some(EK::F10),
+            Keycode::F11 => Some(EK::F11),
+            Keycode::F12 => Some(EK::F12),
+            Keycode::Escape => Some(EK::Escape),
+            Keycode::Space => Some(EK::Space),
+            Keycode::LControl | Keycode::RControl => Some(EK::Control),
+            Keycode::LShift | Keycode::RShift => Some(EK::Shift),
+            Keycode::LAlt | Keycode::RAlt => Some(EK::Alt),
+            Keycode::Meta => Some(EK::Meta),
+            Keycode::Enter => Some(EK::Return),
+            Keycode::Up => Some(EK::UpArrow),
+            Keycode::Down => Some(EK::DownArrow),
+            Keycode::Left => Some(EK::LeftArrow),
+            Keycode::Right => Some(EK::RightArrow),
+            Keycode::Backspace => Some(EK::Backspace),
+            Keycode::CapsLock => Some(EK::CapsLock),
+            Keycode::Tab => Some(EK::Tab),
+            Keycode::Home => Some(EK::Home),
+            Keycode::End => Some(EK::End),
+            Keycode::PageUp => Some(EK::PageUp),
+            Keycode::PageDown => Some(EK::PageDown),
+            Keycode::Delete => Some(EK::Delete),
+            Keycode::NumpadSubtract | Keycode::Minus => layout('-'),
+            Keycode::NumpadAdd => layout('+'),
+            Keycode::NumpadDivide | Keycode::Slash => layout('/'),
+            Keycode::NumpadMultiply => layout('*'),
+            Keycode::Grave => layout('`'),
+            Keycode::Equal => layout('='),
+            Keycode::LeftBracket => layout('['),
+            Keycode::RightBracket => layout(']'),
+            Keycode::BackSlash => layout('\\'),
+            Keycode::Semicolon => layout(';'),
+            Keycode::Apostrophe => layout('\''),
+            Keycode::Comma => layout(','),
+            Keycode::Dot => layout('.'),
+            Keycode::PlatformSystem => {
+                if cfg!(target_os = "macos") {
+                    Some(EK::Meta)
+                } else {
+                    Some(EK::Control)
+                }
+            }
+            // enigo has no cross-platform equivalent for these.
+            Keycode::Insert => None,
+        }
+    }
+}
@@ -1,7 +1,16 @@
+mod backend;
+mod cancel;
+mod capture;
+mod chat;
 mod command;
 mod config;
+mod directive;
+mod fuzzy;
+mod history;
 mod host;
+mod ipc;
 mod keycode;
+mod output;
 mod window;
 
 #[tokio::main]
@@ -12,10 +21,12 @@ async fn main() -> anyhow::Result<()> {
     // process.
     //
     // This is a workaround that should always work by virtue of a new process
-    // being spawned.
+    // being spawned. With `window.persistent`, one window process is kept
+    // around and shown on demand instead; if it can't take focus, the host
+    // goes back to spawning.
     if let Some(args) = std::env::args().nth(1) {
         window::main(&args).await
     } else {
-        host::main()
+        host::main().await
     }
 }
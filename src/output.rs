@@ -0,0 +1,518 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    backend::{Clipboard, Keyboard, SavedClipboard},
+    command::{Chunking, NewlineBehavior, OutputSink},
+    config::Config,
+    ipc::{HostMessage, WindowProcess},
+    keycode::Keycode,
+    window,
+};
+
+/// Sends generated text to each of a command's [`OutputSink`]s.
+///
+/// Sinks that can accept text as it is generated receive it token-by-token
+/// through [`Output::write`]; the rest receive the full text in [`Output::finish`].
+pub struct Output<'a> {
+    sinks: &'a [OutputSink],
+    newline: &'a NewlineBehavior,
+    keyboard: &'a mut dyn Keyboard,
+    clipboard: &'a mut dyn Clipboard,
+    files: Vec<File>,
+    chunkers: Vec<Chunker>,
+    /// The window showing the text as it's generated, unless the user has closed it.
+    window: Option<WindowProcess>,
+    saved_clipboard: Option<SavedClipboard>,
+    injected: Injected,
+    text: String,
+}
+impl<'a> Output<'a> {
+    pub fn new(
+        config: &'a Config,
+        sinks: &'a [OutputSink],
+        newline: &'a NewlineBehavior,
+        keyboard: &'a mut dyn Keyboard,
+        clipboard: &'a mut dyn Clipboard,
+    ) -> anyhow::Result<Self> {
+        let files = sinks
+            .iter()
+            .filter_map(|sink| match sink {
+                OutputSink::File(path) => Some(path),
+                _ => None,
+            })
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .collect::<Result<_, _>>()?;
+        let chunkers = sinks
+            .iter()
+            .filter_map(|sink| match sink {
+                OutputSink::StreamingPaste(chunking) => Some(Chunker::new(*chunking)),
+                _ => None,
+            })
+            .collect();
+        let window = sinks
+            .contains(&OutputSink::Window)
+            .then(|| {
+                WindowProcess::spawn(&display_args(config, window::View::Answer(String::new())))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            sinks,
+            newline,
+            keyboard,
+            clipboard,
+            files,
+            chunkers,
+            window,
+            saved_clipboard: None,
+            injected: Injected::default(),
+            text: String::new(),
+        })
+    }
+
+    /// Writes a newly-generated token to the streaming sinks.
+    ///
+    /// Returns `true` if generation should stop.
+    pub fn write(&mut self, token: &str) -> anyhow::Result<bool> {
+        let (token, stop) = cut_at_newline(self.newline, token);
+        self.text.push_str(token);
+
+        for sink in self.sinks {
+            match sink {
+                OutputSink::Type => {
+                    type_text(self.keyboard, token, self.newline);
+                    self.injected.characters +=
+                        token.chars().filter(|c| *c != '\n' && *c != '\r').count();
+                    self.injected.newlines += token.matches('\n').count();
+                }
+                OutputSink::Stdout => {
+                    let mut stdout = std::io::stdout();
+                    stdout.write_all(token.as_bytes())?;
+                    stdout.flush()?;
+                }
+                OutputSink::Window => {
+                    let sent = self.window.as_mut().map(|window| {
+                        window.send(HostMessage::Output {
+                            text: token.to_string(),
+                        })
+                    });
+                    if let Some(Err(err)) = sent {
+                        // The user can close the window before we're done; that's their call.
+                        eprintln!("Not showing any more output in the window: {err:#}");
+                        self.window = None;
+                    }
+                }
+                OutputSink::Paste
+                | OutputSink::StreamingPaste(_)
+                | OutputSink::Clipboard
+                | OutputSink::File(_) => {}
+            }
+        }
+        for file in &mut self.files {
+            file.write_all(token.as_bytes())?;
+        }
+        for chunker in &mut self.chunkers {
+            if let Some(chunk) = chunker.push(token) {
+                if self.saved_clipboard.is_none() {
+                    self.saved_clipboard = Some(SavedClipboard::save(self.clipboard));
+                }
+                paste_text(self.keyboard, self.clipboard, &chunk)?;
+                self.injected.characters += chunk.chars().count();
+            }
+        }
+
+        Ok(stop)
+    }
+
+    /// Hands the complete text to the sinks that need all of it at once.
+    ///
+    /// Returns what was inserted into the focused application.
+    pub fn finish(mut self) -> anyhow::Result<Injected> {
+        // The user's clipboard is put back before the sinks below run, so that
+        // it doesn't replace the text the `clipboard` sink leaves there.
+        for chunker in &mut self.chunkers {
+            let chunk = chunker.take();
+            if !chunk.is_empty() {
+                paste_text(self.keyboard, self.clipboard, &chunk)?;
+                self.injected.characters += chunk.chars().count();
+            }
+        }
+        if let Some(saved_clipboard) = self.saved_clipboard.take() {
+            saved_clipboard.restore(self.clipboard)?;
+        }
+
+        for sink in self.sinks {
+            match sink {
+                OutputSink::Paste => {
+                    paste(self.keyboard, self.clipboard, &self.text)?;
+                    self.injected.characters += self.text.chars().count();
+                }
+                OutputSink::Clipboard => self.clipboard.set_text(&self.text)?,
+                OutputSink::Window => {
+                    if let Some(mut window) = self.window.take() {
+                        window
+                            .send(HostMessage::Status {
+                                text: "Done".to_string(),
+                            })
+                            .ok();
+                        window.detach();
+                    }
+                }
+                OutputSink::Stdout => println!(),
+                OutputSink::Type | OutputSink::StreamingPaste(_) | OutputSink::File(_) => {}
+            }
+        }
+        for file in &mut self.files {
+            writeln!(file)?;
+        }
+
+        Ok(self.injected)
+    }
+}
+impl Drop for Output<'_> {
+    fn drop(&mut self) {
+        // If generation failed part-way through, the user's clipboard still
+        // needs to be put back.
+        if let Some(saved_clipboard) = self.saved_clipboard.take() {
+            if let Err(err) = saved_clipboard.restore(self.clipboard) {
+                eprintln!("Couldn't restore the clipboard: {err:#}");
+            }
+        }
+    }
+}
+
+/// What an [`Output`] inserted into the focused application.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Injected {
+    /// Characters typed or pasted.
+    pub characters: usize,
+    /// Keypresses made to insert a newline.
+    pub newlines: usize,
+}
+impl Injected {
+    /// The number of backspaces needed to remove what was inserted.
+    pub fn backspaces(&self) -> usize {
+        self.characters + self.newlines
+    }
+}
+
+/// Cuts `token` off at its first newline if `newline` says to stop there.
+///
+/// Returns what's left of the token, and whether generation should stop.
+pub fn cut_at_newline<'t>(newline: &NewlineBehavior, token: &'t str) -> (&'t str, bool) {
+    match (newline, token.find('\n')) {
+        (NewlineBehavior::Stop, Some(index)) => (&token[..index], true),
+        _ => (token, false),
+    }
+}
+
+/// Types out `text`, pressing keys for newlines as described by `newline`.
+fn type_text(keyboard: &mut dyn Keyboard, text: &str, newline: &NewlineBehavior) {
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            match newline {
+                // Text is cut off at the first newline before it gets here.
+                NewlineBehavior::Stop => {}
+                NewlineBehavior::Enter => {
+                    keyboard.key_click(Keycode::Enter);
+                }
+                NewlineBehavior::ShiftEnter => {
+                    keyboard.key_down(Keycode::LShift);
+                    keyboard.key_click(Keycode::Enter);
+                    keyboard.key_up(Keycode::LShift);
+                }
+            }
+        }
+
+        let line = line.trim_end_matches('\r');
+        if !line.is_empty() {
+            keyboard.type_text(line);
+        }
+    }
+}
+
+/// How long to wait after a paste before touching the clipboard again, so that the
+/// application has a chance to read it.
+const PASTE_DELAY: Duration = Duration::from_millis(50);
+
+/// Pastes `text` into the focused application through the clipboard, restoring
+/// the user's clipboard afterwards.
+fn paste(
+    keyboard: &mut dyn Keyboard,
+    clipboard: &mut dyn Clipboard,
+    text: &str,
+) -> anyhow::Result<()> {
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let saved = SavedClipboard::save(clipboard);
+    paste_text(keyboard, clipboard, text)?;
+    saved.restore(clipboard)
+}
+
+/// Pastes `text` into the focused application, leaving it on the clipboard.
+fn paste_text(
+    keyboard: &mut dyn Keyboard,
+    clipboard: &mut dyn Clipboard,
+    text: &str,
+) -> anyhow::Result<()> {
+    clipboard.set_text(text)?;
+
+    keyboard.key_down(Keycode::PlatformSystem);
+    keyboard.key_click(Keycode::V);
+    keyboard.key_up(Keycode::PlatformSystem);
+
+    std::thread::sleep(PASTE_DELAY);
+    Ok(())
+}
+
+/// Buffers generated text until a chunk is ready to be pasted.
+struct Chunker {
+    chunking: Chunking,
+    buffer: String,
+    last_paste: Instant,
+}
+impl Chunker {
+    fn new(chunking: Chunking) -> Self {
+        Self {
+            chunking,
+            buffer: String::new(),
+            last_paste: Instant::now(),
+        }
+    }
+
+    /// Adds `token` to the buffer, returning the chunk to paste if one is ready.
+    fn push(&mut self, token: &str) -> Option<String> {
+        self.buffer.push_str(token);
+
+        let end = match self.chunking {
+            Chunking::Word => self
+                .buffer
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_whitespace())
+                .map(|(index, c)| index + c.len_utf8()),
+            Chunking::Line => self.buffer.rfind('\n').map(|index| index + 1),
+            Chunking::Interval(ms) => (self.last_paste.elapsed() >= Duration::from_millis(ms))
+                .then_some(self.buffer.len()),
+        }?;
+        if end == 0 {
+            return None;
+        }
+
+        self.last_paste = Instant::now();
+        let rest = self.buffer.split_off(end);
+        Some(std::mem::replace(&mut self.buffer, rest))
+    }
+
+    /// Takes whatever is left in the buffer.
+    fn take(&mut self) -> String {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+/// Shows `text` in a popup window. The window is left running until the user dismisses it.
+pub fn show_in_window(config: &Config, text: &str) -> anyhow::Result<()> {
+    WindowProcess::spawn(&display_args(
+        config,
+        window::View::Display(text.to_string()),
+    ))?
+    .detach();
+    Ok(())
+}
+
+fn display_args(config: &Config, view: window::View) -> window::Args {
+    window::Args::new(
+        config,
+        config.window.display_width,
+        config.window.display_height,
+        view,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fakes::{KeyEvent, MemoryClipboard, RecordingKeyboard};
+
+    fn typed(text: &str, newline: NewlineBehavior) -> Vec<KeyEvent> {
+        let keyboard = RecordingKeyboard::default();
+        type_text(&mut keyboard.clone(), text, &newline);
+        keyboard.take_events()
+    }
+
+    fn text(text: &str) -> KeyEvent {
+        KeyEvent::Text(text.to_string())
+    }
+
+    #[test]
+    fn type_text_presses_enter_for_newlines() {
+        assert_eq!(
+            typed("a\nb", NewlineBehavior::Enter),
+            [text("a"), KeyEvent::Click(Keycode::Enter), text("b")]
+        );
+        assert_eq!(
+            typed("a\r\nb", NewlineBehavior::Enter),
+            [text("a"), KeyEvent::Click(Keycode::Enter), text("b")]
+        );
+    }
+
+    #[test]
+    fn type_text_presses_enter_for_a_trailing_newline() {
+        assert_eq!(
+            typed("a\n", NewlineBehavior::Enter),
+            [text("a"), KeyEvent::Click(Keycode::Enter)]
+        );
+    }
+
+    #[test]
+    fn type_text_holds_shift_for_newlines() {
+        assert_eq!(
+            typed("a\n", NewlineBehavior::ShiftEnter),
+            [
+                text("a"),
+                KeyEvent::Down(Keycode::LShift),
+                KeyEvent::Click(Keycode::Enter),
+                KeyEvent::Up(Keycode::LShift),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_text_stops_at_a_newline() {
+        let (token, stop) = cut_at_newline(&NewlineBehavior::Stop, "a\n");
+        assert!(stop);
+        assert_eq!(typed(token, NewlineBehavior::Stop), [text("a")]);
+    }
+
+    /// Starts streaming `tokens` to `sinks`, pasting with a fresh keyboard.
+    fn stream<'a>(
+        config: &'a Config,
+        sinks: &'a [OutputSink],
+        clipboard: &'a mut MemoryClipboard,
+        keyboard: &'a mut RecordingKeyboard,
+        tokens: &[&str],
+    ) -> Output<'a> {
+        let mut output =
+            Output::new(config, sinks, &NewlineBehavior::Enter, keyboard, clipboard).unwrap();
+        for token in tokens {
+            output.write(token).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn streaming_paste_restores_the_clipboard() {
+        let config = Config::default();
+        let sinks = [OutputSink::StreamingPaste(Chunking::Word)];
+        let clipboard = MemoryClipboard::with_text("old");
+        let (mut pasted_to, mut keyboard) = (clipboard.clone(), RecordingKeyboard::default());
+        let output = stream(
+            &config,
+            &sinks,
+            &mut pasted_to,
+            &mut keyboard,
+            &["hello ", "world"],
+        );
+        assert_eq!(clipboard.text().as_deref(), Some("hello "));
+        output.finish().unwrap();
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn streaming_paste_restores_the_clipboard_if_generation_fails() {
+        let config = Config::default();
+        let sinks = [OutputSink::StreamingPaste(Chunking::Word)];
+        let clipboard = MemoryClipboard::with_text("old");
+        let (mut pasted_to, mut keyboard) = (clipboard.clone(), RecordingKeyboard::default());
+        let output = stream(
+            &config,
+            &sinks,
+            &mut pasted_to,
+            &mut keyboard,
+            &["hello ", "world"],
+        );
+        drop(output);
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn clipboard_sink_is_kept_over_the_restored_clipboard() {
+        let config = Config::default();
+        let sinks = [
+            OutputSink::StreamingPaste(Chunking::Word),
+            OutputSink::Clipboard,
+        ];
+        let clipboard = MemoryClipboard::with_text("old");
+        let (mut pasted_to, mut keyboard) = (clipboard.clone(), RecordingKeyboard::default());
+        let output = stream(
+            &config,
+            &sinks,
+            &mut pasted_to,
+            &mut keyboard,
+            &["hello ", "world"],
+        );
+        output.finish().unwrap();
+        assert_eq!(clipboard.text().as_deref(), Some("hello world"));
+    }
+
+    #[test]
+    fn cut_at_newline_only_cuts_when_stopping() {
+        assert_eq!(cut_at_newline(&NewlineBehavior::Stop, "ab"), ("ab", false));
+        assert_eq!(cut_at_newline(&NewlineBehavior::Stop, "a\nb"), ("a", true));
+        assert_eq!(cut_at_newline(&NewlineBehavior::Stop, "\n"), ("", true));
+        assert_eq!(
+            cut_at_newline(&NewlineBehavior::Enter, "a\nb"),
+            ("a\nb", false)
+        );
+    }
+
+    #[test]
+    fn chunker_pastes_whole_words() {
+        let mut chunker = Chunker::new(Chunking::Word);
+        assert_eq!(chunker.push("hel"), None);
+        assert_eq!(chunker.push("lo wor").as_deref(), Some("hello "));
+        assert_eq!(chunker.push("ld"), None);
+        assert_eq!(chunker.push("\n").as_deref(), Some("world\n"));
+        assert_eq!(chunker.take(), "");
+    }
+
+    #[test]
+    fn chunker_pastes_whole_lines() {
+        let mut chunker = Chunker::new(Chunking::Line);
+        assert_eq!(chunker.push("a b"), None);
+        assert_eq!(chunker.push("\nc\nd").as_deref(), Some("a b\nc\n"));
+        assert_eq!(chunker.take(), "d");
+    }
+
+    #[test]
+    fn chunker_pastes_everything_once_the_interval_passes() {
+        let mut chunker = Chunker::new(Chunking::Interval(0));
+        assert_eq!(chunker.push("a b").as_deref(), Some("a b"));
+
+        let mut chunker = Chunker::new(Chunking::Interval(60_000));
+        assert_eq!(chunker.push("a b"), None);
+        assert_eq!(chunker.take(), "a b");
+    }
+
+    #[test]
+    fn paste_restores_the_clipboard() {
+        let keyboard = RecordingKeyboard::default();
+        let clipboard = MemoryClipboard::with_text("old");
+        paste(&mut keyboard.clone(), &mut clipboard.clone(), "new").unwrap();
+        assert_eq!(
+            keyboard.take_events(),
+            [
+                KeyEvent::Down(Keycode::PlatformSystem),
+                KeyEvent::Click(Keycode::V),
+                KeyEvent::Up(Keycode::PlatformSystem),
+            ]
+        );
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+}
@@ -1,18 +1,136 @@
-use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+use crate::{
+    config::Config,
+    directive::{self, Completion},
+    ipc::{self, HostMessage, WindowMessage},
+};
+
+mod appearance;
+mod chat;
+mod gpu;
+mod highlight;
+mod history;
+mod ime;
+mod markdown;
+mod palette;
+mod software;
+
+pub use appearance::Appearance;
+use chat::Chat;
+use history::History;
+use ime::InputMethod;
+use palette::Palette;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Args {
     pub width: u32,
     pub height: u32,
+    /// Start hidden, and hide instead of exiting once the user is done. The host
+    /// shows the window again with [`HostMessage::Show`].
+    #[serde(default)]
+    pub persistent: bool,
+    #[serde(default)]
+    pub renderer: Renderer,
+    #[serde(default)]
+    pub appearance: Appearance,
+    pub view: View,
+}
+impl Args {
+    /// A window of `width` by `height` showing `view`, drawn and styled as the
+    /// config says.
+    pub fn new(config: &Config, width: u32, height: u32, view: View) -> Self {
+        Self {
+            width,
+            height,
+            persistent: false,
+            renderer: config.window.renderer,
+            appearance: config.window.appearance.clone(),
+            view,
+        }
+    }
+}
+
+/// How the window is drawn.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Renderer {
+    /// Use the GPU if there's one available, and draw on the CPU otherwise.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    /// Always use the GPU.
+    #[serde(rename = "gpu")]
+    Gpu,
+    /// Always draw on the CPU. Slower, but works on machines without a GPU,
+    /// like headless VMs and remote desktops.
+    #[serde(rename = "software")]
+    Software,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum View {
+    /// Ask the user for a single line of input, which is sent back to the host.
+    SingleLine,
+    /// Ask the user for any amount of input in a resizable editor. Ctrl+Enter
+    /// submits it; Enter starts a new line.
+    MultiLine(SizeLimits),
+    /// Show some text until the user dismisses it. The host can add to the text
+    /// while the window is open.
+    Display(String),
+    /// Show generated text rendered as Markdown until the user dismisses it.
+    /// The host adds to the text as it's generated. The user can pin the
+    /// window to keep it on top while they carry on working.
+    Answer(String),
+    /// Show the output as the host generates it, and let the user edit it and
+    /// then accept, copy, regenerate or discard it.
+    Preview,
+    /// Show how far along the host is with loading the model.
+    Progress(String),
+    /// Let the user pick one of these commands, then enter a prompt for it.
+    Palette(Vec<PaletteEntry>),
+    /// Hold a conversation with the model. The host sends the messages, and
+    /// streams in the replies.
+    Chat,
+}
+
+/// A command listed in the command palette.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PaletteEntry {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// How small and large the user can make a resizable window, in logical pixels.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SizeLimits {
+    pub min_width: u32,
+    pub min_height: u32,
+    pub max_width: u32,
+    pub max_height: u32,
 }
 
 pub(super) async fn main(args: &str) -> anyhow::Result<()> {
-    use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+    let result = run(args).await;
+    if let Err(err) = &result {
+        send(WindowMessage::Error {
+            message: format!("{err:#}"),
+        });
+    }
+    result
+}
+
+/// Sends a message to the host.
+fn send(message: WindowMessage) {
+    if let Err(err) = ipc::write(std::io::stdout(), message) {
+        eprintln!("Couldn't send a message to the host: {err:#}");
+    }
+}
+
+async fn run(args: &str) -> anyhow::Result<()> {
     use egui_winit_platform::{Platform, PlatformDescriptor};
     use winit::{event::Event, event_loop::ControlFlow};
 
-    let args: Args = serde_json::from_str(args)?;
+    let mut args: Args = serde_json::from_str(args)?;
 
     let event_loop = {
         let mut builder = winit::event_loop::EventLoopBuilder::<WinitEvent>::with_user_event();
@@ -28,186 +146,626 @@ pub(super) async fn main(args: &str) -> anyhow::Result<()> {
         builder.build()
     };
 
-    let window = winit::window::WindowBuilder::new()
+    let mut window_builder = winit::window::WindowBuilder::new();
+    if let View::MultiLine(limits) = &args.view {
+        window_builder = window_builder
+            .with_min_inner_size(winit::dpi::LogicalSize {
+                width: limits.min_width,
+                height: limits.min_height,
+            })
+            .with_max_inner_size(winit::dpi::LogicalSize {
+                width: limits.max_width,
+                height: limits.max_height,
+            });
+    }
+    let size = winit::dpi::LogicalSize {
+        width: args.width,
+        height: args.height,
+    };
+    if let Some(position) = args
+        .appearance
+        .position(size, event_loop.available_monitors())
+    {
+        window_builder = window_builder.with_position(position);
+    }
+    let window = window_builder
         .with_decorations(false)
-        .with_resizable(false)
+        .with_resizable(matches!(
+            args.view,
+            View::Display(_)
+                | View::Answer(_)
+                | View::MultiLine(_)
+                | View::Palette(_)
+                | View::Preview
+                | View::Chat
+        ))
         .with_transparent(true)
         .with_title("alpa")
-        .with_visible(true)
-        .with_active(true)
-        .with_window_level(winit::window::WindowLevel::AlwaysOnTop)
-        .with_inner_size(winit::dpi::LogicalSize {
-            width: args.width,
-            height: args.height,
+        .with_visible(!args.persistent)
+        // Windows that only show output mustn't take focus, as the output may
+        // also be being typed or pasted into the application that has it.
+        .with_active(!args.persistent && !matches!(args.view, View::Display(_) | View::Answer(_)))
+        // Answers only stay on top once they're pinned.
+        .with_window_level(match args.view {
+            View::Answer(_) => winit::window::WindowLevel::Normal,
+            _ => winit::window::WindowLevel::AlwaysOnTop,
         })
+        .with_inner_size(size)
         .build(&event_loop)?;
 
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
-        dx12_shader_compiler: Default::default(),
-    });
-    let surface = unsafe { instance.create_surface(&window) }.unwrap();
-    let size = window.inner_size();
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::LowPower,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        })
-        .await
-        .context("no adapter")?;
-
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-            },
-            None,
-        )
-        .await?;
-    let surface_caps = surface.get_capabilities(&adapter);
-    let surface_format = surface_caps
-        .formats
-        .iter()
-        .copied()
-        .find(|f| f.describe().srgb)
-        .unwrap_or(surface_caps.formats[0]);
-    let mut surface_config = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width: size.width,
-        height: size.height,
-        present_mode: surface_caps.present_modes[0],
-        alpha_mode: surface_caps.alpha_modes[0],
-        view_formats: vec![],
+    let mut painter: Box<dyn Painter> = match args.renderer {
+        Renderer::Gpu => Box::new(gpu::GpuPainter::new(&window).await?),
+        Renderer::Software => Box::new(software::SoftwarePainter::new(&window)?),
+        Renderer::Auto => match gpu::GpuPainter::new(&window).await {
+            Ok(painter) => Box::new(painter),
+            Err(err) => {
+                eprintln!("Falling back to software rendering: {err:#}");
+                Box::new(software::SoftwarePainter::new(&window)?)
+            }
+        },
     };
-    surface.configure(&device, &surface_config);
 
     // We use the egui_winit_platform crate as the platform.
+    let physical_size = window.inner_size();
     let mut platform = Platform::new(PlatformDescriptor {
-        physical_width: args.width,
-        physical_height: args.height,
+        physical_width: physical_size.width,
+        physical_height: physical_size.height,
         scale_factor: window.scale_factor(),
         ..Default::default()
     });
+    args.appearance.apply(&platform.context())?;
 
-    // We use the egui_wgpu_backend crate as the render backend.
-    let mut egui_rpass = RenderPass::new(&device, surface_format, 1);
+    // Forward messages from the host to the event loop.
+    let proxy = event_loop.create_proxy();
+    ipc::read_in_background(std::io::stdin(), move |message| {
+        proxy.send_event(WinitEvent::Host(message)).is_ok()
+    });
+    // Let the host know the event loop is still running, even when the user
+    // isn't doing anything.
+    let proxy = event_loop.create_proxy();
+    std::thread::spawn(move || {
+        while proxy.send_event(WinitEvent::Heartbeat).is_ok() {
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+        }
+    });
 
     let start_time = std::time::Instant::now();
     let mut input = String::new();
+    let input_id = egui::Id::new("input");
+    let mut history = History::default();
+    // The names of the models and commands that directives can refer to.
+    let mut completions: (Vec<String>, Vec<String>) = Default::default();
+    let mut palette = match &args.view {
+        View::Palette(entries) => Some(Palette::new(entries.clone())),
+        _ => None,
+    };
+    let mut answer = match &args.view {
+        View::Answer(text) => markdown::parse(text),
+        _ => vec![],
+    };
+    let mut pinned = false;
+    let mut chat = Chat::default();
+    let mut status: Option<String> = None;
+    let mut progress: Option<f32> = None;
+    // How many tokens the prompt uses, and how many the model can take.
+    let mut token_usage: Option<(usize, usize)> = None;
+    // Whether the host is still adding to the preview.
+    let mut generating = matches!(args.view, View::Preview);
+    let mut visible = !args.persistent;
+    let mut input_method = InputMethod::default();
 
     event_loop.run(move |event, _window_target, control_flow| {
-        // Pass the winit events to the platform integration.
-        platform.handle_event(&event);
+        // Pass the winit events to the platform integration, apart from those
+        // meant for the IME.
+        let for_platform = match &event {
+            Event::WindowEvent { event, .. } => input_method.handle_event(&mut platform, event),
+            _ => true,
+        };
+        if for_platform {
+            platform.handle_event(&event);
+        }
 
         match event {
             Event::RedrawRequested(..) => {
                 platform.update_time(start_time.elapsed().as_secs_f64());
 
-                let output_frame = match surface.get_current_texture() {
-                    Ok(frame) => frame,
-                    Err(wgpu::SurfaceError::Outdated) => {
-                        // This error occurs when the app is minimized on Windows.
-                        // Silently return here to prevent spamming the console with:
-                        // "The underlying surface has changed, and therefore the swap chain must be updated"
-                        return;
-                    }
-                    Err(e) => {
-                        eprintln!("Dropped frame with error: {}", e);
-                        return;
-                    }
-                };
-                let output_view = output_frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-
                 // Begin to draw the UI frame.
                 platform.begin_frame();
 
-                egui::CentralPanel::default().show(&platform.context(), |ui| {
-                    let input_widget = egui::TextEdit::singleline(&mut input).lock_focus(true);
-                    let input_res = ui.add_sized(ui.available_size(), input_widget);
+                // Set once the user has submitted or dismissed their input.
+                let mut done = false;
 
-                    input_res.request_focus();
+                let frame = args.appearance.frame(&platform.context().style());
+                egui::CentralPanel::default()
+                    .frame(frame)
+                    .show(&platform.context(), |ui| match &args.view {
+                        View::SingleLine => {
+                            if history.is_searching() {
+                                if history.search_ui(ui, &mut input) {
+                                    history::move_cursor_to_end(ui, input_id, &input);
+                                    send(WindowMessage::PartialText {
+                                        text: input.clone(),
+                                    });
+                                }
+                                return;
+                            }
 
-                    ui.input(|i| {
-                        if i.key_released(egui::Key::Escape) {
-                            *control_flow = ControlFlow::Exit;
+                            history.start_search(ui);
+                            let mut replaced = history.recall(ui, &mut input);
+
+                            // Tab accepts the suggested directive or value.
+                            let mut completion =
+                                directive::complete(&input, &completions.0, &completions.1);
+                            if let Some(Completion::Insert(suffix)) = &completion {
+                                if ui.input_mut(|i| {
+                                    i.consume_key(egui::Modifiers::NONE, egui::Key::Tab)
+                                }) {
+                                    input.push_str(suffix);
+                                    replaced = true;
+                                    completion =
+                                        directive::complete(&input, &completions.0, &completions.1);
+                                }
+                            }
+                            if replaced {
+                                history::move_cursor_to_end(ui, input_id, &input);
+                            }
+
+                            let input_widget = egui::TextEdit::singleline(&mut input)
+                                .id(input_id)
+                                .lock_focus(true);
+                            let input_output = ui
+                                .allocate_ui_with_layout(
+                                    ui.available_size(),
+                                    egui::Layout::centered_and_justified(ui.layout().main_dir()),
+                                    |ui| input_widget.show(ui),
+                                )
+                                .inner;
+                            let input_res = input_output.response;
+                            if let Some(usage) = token_usage {
+                                show_token_usage(
+                                    ui,
+                                    input_res.rect.shrink(ui.spacing().button_padding.x),
+                                    egui::Align2::RIGHT_CENTER,
+                                    usage,
+                                );
+                            }
+
+                            // Show the suggestion after what's been typed, inside the text field.
+                            let suggestion = match &completion {
+                                Some(Completion::Insert(suffix)) => Some(suffix.as_str()),
+                                Some(Completion::Hint(hint)) => Some(*hint),
+                                None => None,
+                            };
+                            if let Some(suggestion) = suggestion {
+                                ui.painter().text(
+                                    input_output.text_draw_pos
+                                        + input_output.galley.rect.right_top().to_vec2(),
+                                    egui::Align2::LEFT_TOP,
+                                    suggestion,
+                                    egui::TextStyle::Body.resolve(ui.style()),
+                                    ui.visuals().weak_text_color(),
+                                );
+                            }
+
+                            input_res.request_focus();
+                            if replaced || input_res.changed() {
+                                send(WindowMessage::PartialText {
+                                    text: input.clone(),
+                                });
+                            }
+
+                            ui.input(|i| {
+                                if i.key_released(egui::Key::Escape) {
+                                    send(WindowMessage::Cancel);
+                                    done = true;
+                                }
+
+                                if i.key_released(egui::Key::Enter) {
+                                    send(WindowMessage::Submit {
+                                        text: input.clone(),
+                                    });
+                                    done = true;
+                                }
+                            });
                         }
+                        View::MultiLine(_) => {
+                            let searching = history.is_searching();
+                            let mut recalled = false;
+                            if searching {
+                                recalled = history.search_ui(ui, &mut input);
+                            } else {
+                                history.start_search(ui);
+                                if history.is_recalling(&input) {
+                                    recalled = history.recall(ui, &mut input);
+                                }
+                            }
+                            if recalled {
+                                history::move_cursor_to_end(ui, input_id, &input);
+                            }
 
-                        if i.key_released(egui::Key::Enter) {
-                            print!("{input}");
-                            *control_flow = ControlFlow::Exit;
+                            // This has to be taken before the editor sees it, or it'll become a newline.
+                            let submit = !searching
+                                && ui.input_mut(|i| {
+                                    i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)
+                                });
+
+                            let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                                let mut job = highlight::highlight(ui, text);
+                                job.wrap.max_width = wrap_width;
+                                ui.fonts(|f| f.layout_job(job))
+                            };
+                            let input_widget = egui::TextEdit::multiline(&mut input)
+                                .id(input_id)
+                                .code_editor()
+                                .desired_width(f32::INFINITY)
+                                .layouter(&mut layouter);
+                            let scroll_output = egui::ScrollArea::vertical()
+                                .show(ui, |ui| ui.add_sized(ui.available_size(), input_widget));
+                            let input_res = scroll_output.inner;
+                            if let Some(usage) = token_usage {
+                                show_token_usage(
+                                    ui,
+                                    scroll_output.inner_rect.shrink(ui.spacing().item_spacing.x),
+                                    egui::Align2::RIGHT_BOTTOM,
+                                    usage,
+                                );
+                            }
+
+                            if !searching {
+                                input_res.request_focus();
+                            }
+                            if recalled || input_res.changed() {
+                                send(WindowMessage::PartialText {
+                                    text: input.clone(),
+                                });
+                            }
+
+                            if submit {
+                                send(WindowMessage::Submit {
+                                    text: input.clone(),
+                                });
+                                done = true;
+                            }
+                            if !searching && ui.input(|i| i.key_released(egui::Key::Escape)) {
+                                send(WindowMessage::Cancel);
+                                done = true;
+                            }
+                        }
+                        View::Display(text) => {
+                            // The window doesn't take focus, so it needs a way
+                            // to be closed without clicking into it first.
+                            ui.horizontal(|ui| {
+                                if let Some(status) = &status {
+                                    ui.weak(status);
+                                }
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                                            *control_flow = ControlFlow::Exit;
+                                        }
+                                    },
+                                );
+                            });
+                            egui::ScrollArea::vertical()
+                                .stick_to_bottom(true)
+                                .show(ui, |ui| {
+                                    ui.add(egui::Label::new(text.as_str()).wrap(true));
+                                });
+
+                            ui.input(|i| {
+                                if i.key_released(egui::Key::Escape)
+                                    || i.key_released(egui::Key::Enter)
+                                {
+                                    *control_flow = ControlFlow::Exit;
+                                }
+                            });
+                        }
+                        View::Preview => {
+                            let (mut accept, mut regenerate) = ui.input_mut(|i| {
+                                (
+                                    i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter),
+                                    i.consume_key(egui::Modifiers::COMMAND, egui::Key::R),
+                                )
+                            });
+                            let mut copy = false;
+                            let mut discard = false;
+                            ui.horizontal(|ui| {
+                                accept |= ui.button("Accept").on_hover_text("Ctrl+Enter").clicked();
+                                copy = ui.button("Copy").clicked();
+                                regenerate |=
+                                    ui.button("Regenerate").on_hover_text("Ctrl+R").clicked();
+                                discard = ui.button("Discard").on_hover_text("Escape").clicked();
+                                if generating {
+                                    ui.spinner();
+                                }
+                            });
+
+                            // Editing is only allowed once the host has stopped adding to the text.
+                            let output_widget = egui::TextEdit::multiline(&mut input)
+                                .id(input_id)
+                                .desired_width(f32::INFINITY)
+                                .interactive(!generating);
+                            egui::ScrollArea::vertical()
+                                .stick_to_bottom(generating)
+                                .show(ui, |ui| ui.add_sized(ui.available_size(), output_widget));
+                            discard |= ui.input(|i| i.key_released(egui::Key::Escape));
+
+                            if accept {
+                                send(WindowMessage::Submit {
+                                    text: input.clone(),
+                                });
+                                done = true;
+                            } else if copy {
+                                send(WindowMessage::Copy {
+                                    text: input.clone(),
+                                });
+                                done = true;
+                            } else if regenerate {
+                                send(WindowMessage::Regenerate);
+                            } else if discard {
+                                send(WindowMessage::Cancel);
+                                done = true;
+                            }
+                        }
+                        View::Answer(text) => {
+                            ui.horizontal(|ui| {
+                                if let Some(status) = &status {
+                                    ui.weak(status);
+                                }
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        if ui.small_button("✕").on_hover_text("Close").clicked() {
+                                            *control_flow = ControlFlow::Exit;
+                                        }
+                                        if ui.small_button("Copy").clicked() {
+                                            ui.output_mut(|o| o.copied_text = text.clone());
+                                        }
+                                        let pin =
+                                            ui.toggle_value(&mut pinned, "Pin").on_hover_text(
+                                                "Keep this window on top while you work",
+                                            );
+                                        if pin.changed() {
+                                            window.set_window_level(if pinned {
+                                                winit::window::WindowLevel::AlwaysOnTop
+                                            } else {
+                                                winit::window::WindowLevel::Normal
+                                            });
+                                        }
+                                    },
+                                );
+                            });
+                            egui::ScrollArea::vertical()
+                                .stick_to_bottom(true)
+                                .show(ui, |ui| markdown::show(ui, &answer));
+
+                            // A pinned answer has to be closed on purpose.
+                            if !pinned && ui.input(|i| i.key_released(egui::Key::Escape)) {
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
+                        View::Chat => {
+                            if let Some(message) = chat.ui(ui, status.as_deref()) {
+                                done = matches!(message, WindowMessage::Cancel);
+                                send(message);
+                            }
+                        }
+                        View::Palette(_) => {
+                            let action = palette.as_mut().and_then(|palette| palette.ui(ui));
+                            match action {
+                                Some(palette::Action::Pick(command, text)) => {
+                                    send(WindowMessage::Pick { command, text });
+                                    done = true;
+                                }
+                                Some(palette::Action::Cancel) => {
+                                    send(WindowMessage::Cancel);
+                                    done = true;
+                                }
+                                None => {}
+                            }
+                        }
+                        View::Progress(label) => {
+                            ui.label(label.as_str());
+                            ui.add(
+                                egui::ProgressBar::new(progress.unwrap_or_default())
+                                    .show_percentage(),
+                            );
                         }
                     });
-                });
 
                 // End the UI frame. We could now handle the output and draw the UI with the backend.
                 let full_output = platform.end_frame(Some(&window));
+                input_method.update(&window, &full_output.platform_output);
                 let paint_jobs = platform.context().tessellate(full_output.shapes);
 
-                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("encoder"),
-                });
-
-                // Upload all resources for the GPU.
-                let screen_descriptor = ScreenDescriptor {
-                    physical_width: surface_config.width,
-                    physical_height: surface_config.height,
-                    scale_factor: window.scale_factor() as f32,
-                };
-                let tdelta: egui::TexturesDelta = full_output.textures_delta;
-                egui_rpass
-                    .add_textures(&device, &queue, &tdelta)
-                    .expect("add texture ok");
-                egui_rpass.update_buffers(&device, &queue, &paint_jobs, &screen_descriptor);
-
-                // Record all render passes.
-                egui_rpass
-                    .execute(
-                        &mut encoder,
-                        &output_view,
-                        &paint_jobs,
-                        &screen_descriptor,
-                        Some(wgpu::Color::BLACK),
-                    )
-                    .unwrap();
-                // Submit the commands.
-                queue.submit(std::iter::once(encoder.finish()));
-
-                // Redraw egui
-                output_frame.present();
-
-                egui_rpass
-                    .remove_textures(tdelta)
-                    .expect("remove texture ok");
+                if let Err(e) = painter.paint(
+                    window.scale_factor() as f32,
+                    &paint_jobs,
+                    full_output.textures_delta,
+                ) {
+                    eprintln!("Dropped frame with error: {e:#}");
+                }
+
+                // Only draw again when egui asks to, such as for an animation,
+                // or when something happens. Redrawing all the time would keep
+                // a core busy, especially with the software renderer.
+                if !visible {
+                    *control_flow = ControlFlow::Wait;
+                } else if full_output.repaint_after.is_zero() {
+                    window.request_redraw();
+                } else {
+                    *control_flow = std::time::Instant::now()
+                        .checked_add(full_output.repaint_after)
+                        .map_or(ControlFlow::Wait, ControlFlow::WaitUntil);
+                }
+
+                if done {
+                    if args.persistent {
+                        window.set_visible(false);
+                        visible = false;
+                        input.clear();
+                    } else {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
             }
-            Event::MainEventsCleared | Event::UserEvent(WinitEvent::RequestRedraw) => {
+            Event::UserEvent(WinitEvent::Host(message)) => {
+                match message {
+                    HostMessage::Status { text } => status = Some(text),
+                    HostMessage::Output { text } => match &mut args.view {
+                        View::Display(display) => display.push_str(&text),
+                        View::Answer(text_so_far) => {
+                            text_so_far.push_str(&text);
+                            answer = markdown::parse(text_so_far);
+                        }
+                        View::Preview => input.push_str(&text),
+                        View::Chat => chat.add_to_reply(&text),
+                        _ => {}
+                    },
+                    HostMessage::Generating => {
+                        generating = true;
+                        match &args.view {
+                            View::Preview => input.clear(),
+                            View::Chat => chat.start_reply(),
+                            _ => {}
+                        }
+                    }
+                    HostMessage::Generated => {
+                        generating = false;
+                        chat.finish_reply();
+                    }
+                    HostMessage::Chat { messages } => chat.set_messages(messages),
+                    HostMessage::LoadProgress { fraction } => progress = Some(fraction),
+                    HostMessage::TokenUsage { used, limit } => token_usage = Some((used, limit)),
+                    HostMessage::History { entries } => history = History::new(entries),
+                    HostMessage::Completions { models, commands } => {
+                        completions = (models, commands)
+                    }
+                    HostMessage::Show => {
+                        input.clear();
+                        // The cursor may have moved since the window was last shown.
+                        if let Some(position) =
+                            args.appearance.position(size, window.available_monitors())
+                        {
+                            window.set_outer_position(position);
+                        }
+                        window.set_visible(true);
+                        window.focus_window();
+                        visible = true;
+                    }
+                    HostMessage::Hide => {
+                        window.set_visible(false);
+                        visible = false;
+                    }
+                    HostMessage::Close => *control_flow = ControlFlow::Exit,
+                }
                 window.request_redraw();
             }
-            Event::WindowEvent { event, .. } => match event {
-                winit::event::WindowEvent::Resized(size) => {
-                    if size.width > 0 && size.height > 0 {
-                        surface_config.width = size.width;
-                        surface_config.height = size.height;
-                        surface.configure(&device, &surface_config);
-                    }
+            Event::UserEvent(WinitEvent::Heartbeat) => send(WindowMessage::Alive),
+            Event::NewEvents(winit::event::StartCause::Init) => {
+                *control_flow = ControlFlow::Wait;
+            }
+            // egui asked to be drawn again after a while.
+            Event::NewEvents(winit::event::StartCause::ResumeTimeReached { .. })
+            | Event::UserEvent(WinitEvent::RequestRedraw) => {
+                // There's no point drawing a window that's been hidden, so sleep
+                // until the host needs it again.
+                if visible {
+                    window.request_redraw();
                 }
-                winit::event::WindowEvent::CloseRequested => {
-                    *control_flow = ControlFlow::Exit;
+            }
+            Event::WindowEvent { event, .. } => {
+                // Input changes what's shown, and egui needs a frame to see it.
+                if visible {
+                    window.request_redraw();
                 }
-                _ => {}
-            },
+                match event {
+                    winit::event::WindowEvent::Resized(size) => {
+                        if size.width > 0 && size.height > 0 {
+                            painter.resize(size.width, size.height);
+                        }
+                    }
+                    winit::event::WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        painter.resize(new_inner_size.width, new_inner_size.height);
+                    }
+                    winit::event::WindowEvent::Focused(focused) => {
+                        send(WindowMessage::Focused { focused });
+                    }
+                    winit::event::WindowEvent::CloseRequested => {
+                        if matches!(
+                            args.view,
+                            View::SingleLine
+                                | View::MultiLine(_)
+                                | View::Palette(_)
+                                | View::Preview
+                                | View::Chat
+                        ) {
+                            send(WindowMessage::Cancel);
+                        }
+                        if args.persistent {
+                            window.set_visible(false);
+                            visible = false;
+                            input.clear();
+                        } else {
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                    _ => {}
+                }
+            }
             _ => (),
         }
     });
 }
 
+/// Shows how many tokens the prompt uses in the `align` corner of `rect`,
+/// warning as it gets close to not fitting in the context.
+fn show_token_usage(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    align: egui::Align2,
+    (used, limit): (usize, usize),
+) {
+    let visuals = ui.visuals();
+    let (text, color) = if used >= limit {
+        (
+            format!("{used}/{limit} tokens: too long for the context"),
+            visuals.error_fg_color,
+        )
+    } else if used * 10 >= limit * 9 {
+        (format!("{used}/{limit} tokens"), visuals.warn_fg_color)
+    } else {
+        (format!("{used}/{limit} tokens"), visuals.weak_text_color())
+    };
+    ui.painter().text(
+        align.pos_in_rect(&rect),
+        align,
+        text,
+        egui::TextStyle::Small.resolve(ui.style()),
+        color,
+    );
+}
+
+/// Draws egui's output to the window.
+trait Painter {
+    /// Called when the window's size in physical pixels changes.
+    fn resize(&mut self, width: u32, height: u32);
+    fn paint(
+        &mut self,
+        scale_factor: f32,
+        paint_jobs: &[egui::ClippedPrimitive],
+        textures_delta: egui::TexturesDelta,
+    ) -> anyhow::Result<()>;
+}
+
+/// How often the window tells the host it's still alive.
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 enum WinitEvent {
     RequestRedraw,
+    Heartbeat,
+    Host(HostMessage),
 }
 
 struct EguiRepaintSignal(std::sync::Mutex<winit::event_loop::EventLoopProxy<WinitEvent>>);
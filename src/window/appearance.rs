@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize, Position},
+    monitor::MonitorHandle,
+};
+
+/// How windows look, and where they appear.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Appearance {
+    #[serde(default)]
+    pub placement: Placement,
+    #[serde(default)]
+    pub theme: Theme,
+    /// How opaque the window's background is, from 0 to 1. Only the GPU
+    /// renderer can draw see-through windows.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// A TTF or OTF file to use for text instead of the built-in font.
+    #[serde(default)]
+    pub font: Option<PathBuf>,
+    /// A TTF or OTF file to use for code and the editor instead of the
+    /// built-in monospace font.
+    #[serde(default)]
+    pub monospace_font: Option<PathBuf>,
+    /// The size of body text, in points. Headings and small text are scaled to match.
+    #[serde(default)]
+    pub font_size: Option<f32>,
+    /// The space between the edge of the window and its contents, in points.
+    #[serde(default = "default_padding")]
+    pub padding: f32,
+}
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            placement: Placement::default(),
+            theme: Theme::default(),
+            opacity: default_opacity(),
+            font: None,
+            monospace_font: None,
+            font_size: None,
+            padding: default_padding(),
+        }
+    }
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_padding() -> f32 {
+    8.0
+}
+
+/// Where a window appears when it's opened.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub enum Placement {
+    /// In the middle of the monitor the mouse cursor is on.
+    #[default]
+    #[serde(rename = "centre")]
+    Centre,
+    /// Just below and to the right of the mouse cursor, kept on its monitor.
+    #[serde(rename = "cursor")]
+    Cursor,
+    /// With its top-left corner at these coordinates, in points.
+    #[serde(rename = "fixed")]
+    Fixed { x: i32, y: i32 },
+    /// Wherever the platform puts it.
+    #[serde(rename = "platform")]
+    Platform,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub enum Theme {
+    #[default]
+    #[serde(rename = "dark")]
+    Dark,
+    #[serde(rename = "light")]
+    Light,
+    /// The dark or light theme with some of its colours replaced.
+    #[serde(rename = "custom")]
+    Custom(CustomTheme),
+}
+
+/// Colours are written like `#1e1e2e`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CustomTheme {
+    /// Start from the light theme rather than the dark one.
+    #[serde(default)]
+    pub light: bool,
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Used for selections and links.
+    #[serde(default)]
+    pub accent: Option<String>,
+}
+
+impl Appearance {
+    /// Applies the theme, fonts and opacity to `ctx`. Fails if a colour or
+    /// font can't be used.
+    pub fn apply(&self, ctx: &egui::Context) -> anyhow::Result<()> {
+        let mut visuals = match &self.theme {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+            Theme::Custom(custom) => {
+                let mut visuals = if custom.light {
+                    egui::Visuals::light()
+                } else {
+                    egui::Visuals::dark()
+                };
+                if let Some(background) = &custom.background {
+                    let background = parse_color(background)?;
+                    visuals.panel_fill = background;
+                    visuals.window_fill = background;
+                }
+                if let Some(text) = &custom.text {
+                    visuals.override_text_color = Some(parse_color(text)?);
+                }
+                if let Some(accent) = &custom.accent {
+                    let accent = parse_color(accent)?;
+                    visuals.selection.bg_fill = accent;
+                    visuals.hyperlink_color = accent;
+                }
+                visuals
+            }
+        };
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        visuals.panel_fill = visuals.panel_fill.linear_multiply(opacity);
+        visuals.window_fill = visuals.window_fill.linear_multiply(opacity);
+        ctx.set_visuals(visuals);
+
+        if self.font.is_some() || self.monospace_font.is_some() {
+            let mut fonts = egui::FontDefinitions::default();
+            for (path, family) in [
+                (&self.font, egui::FontFamily::Proportional),
+                (&self.monospace_font, egui::FontFamily::Monospace),
+            ] {
+                let Some(path) = path else {
+                    continue;
+                };
+                let data = std::fs::read(path)
+                    .with_context(|| format!("couldn't read font {}", path.display()))?;
+                let name = path.display().to_string();
+                fonts
+                    .font_data
+                    .insert(name.clone(), egui::FontData::from_owned(data));
+                // Fall back to the built-in fonts for anything this one doesn't have.
+                fonts.families.entry(family).or_default().insert(0, name);
+            }
+            ctx.set_fonts(fonts);
+        }
+
+        if let Some(size) = self.font_size {
+            let mut style = (*ctx.style()).clone();
+            for (text_style, font_id) in &mut style.text_styles {
+                font_id.size = match text_style {
+                    egui::TextStyle::Heading => size * 1.4,
+                    egui::TextStyle::Small => size * 0.75,
+                    _ => size,
+                };
+            }
+            ctx.set_style(style);
+        }
+        Ok(())
+    }
+
+    /// The frame around the window's contents.
+    pub fn frame(&self, style: &egui::Style) -> egui::Frame {
+        egui::Frame::central_panel(style).inner_margin(self.padding)
+    }
+
+    /// Works out where a window of `size` should go, if anywhere in particular.
+    pub fn position(
+        &self,
+        size: LogicalSize<u32>,
+        monitors: impl Iterator<Item = MonitorHandle>,
+    ) -> Option<Position> {
+        let cursor = match self.placement {
+            Placement::Platform => return None,
+            Placement::Fixed { x, y } => return Some(LogicalPosition::new(x, y).into()),
+            Placement::Centre | Placement::Cursor => {
+                device_query::DeviceState::new().get_mouse().coords
+            }
+        };
+
+        // Monitors have their own scale factors, so this is all done in the
+        // physical pixels of the monitor the window will be on.
+        let monitors: Vec<_> = monitors.collect();
+        let (monitor, cursor) = monitors
+            .iter()
+            .find_map(|monitor| Some((monitor, cursor_on(monitor, cursor)?)))
+            .or_else(|| {
+                let monitor = monitors.first()?;
+                Some((monitor, cursor_in_pixels(monitor, cursor)))
+            })?;
+        let area = monitor.position();
+        let area_size = monitor.size();
+        let size: PhysicalSize<i32> = size.to_physical(monitor.scale_factor());
+        let (right, bottom) = (
+            area.x + area_size.width as i32 - size.width,
+            area.y + area_size.height as i32 - size.height,
+        );
+
+        let (x, y) = match self.placement {
+            Placement::Cursor => {
+                let offset = (16.0 * monitor.scale_factor()) as i32;
+                (
+                    (cursor.x + offset).min(right).max(area.x),
+                    (cursor.y + offset).min(bottom).max(area.y),
+                )
+            }
+            _ => ((area.x + right) / 2, (area.y + bottom) / 2),
+        };
+        Some(PhysicalPosition::new(x, y).into())
+    }
+}
+
+/// The cursor's position in physical pixels, if it's on `monitor`.
+fn cursor_on(monitor: &MonitorHandle, cursor: (i32, i32)) -> Option<PhysicalPosition<i32>> {
+    let cursor = cursor_in_pixels(monitor, cursor);
+    let (position, size) = (monitor.position(), monitor.size());
+    ((position.x..position.x + size.width as i32).contains(&cursor.x)
+        && (position.y..position.y + size.height as i32).contains(&cursor.y))
+    .then_some(cursor)
+}
+
+/// Converts the cursor position from device_query into the physical pixels
+/// that winit uses for `monitor`. On macOS the cursor is in points, which
+/// are scaled by the monitor's scale factor relative to its own origin.
+fn cursor_in_pixels(monitor: &MonitorHandle, (x, y): (i32, i32)) -> PhysicalPosition<i32> {
+    if !cfg!(target_os = "macos") {
+        return PhysicalPosition::new(x, y);
+    }
+    let scale = monitor.scale_factor();
+    let origin = monitor.position();
+    let logical_origin: LogicalPosition<f64> = origin.to_logical(scale);
+    PhysicalPosition::new(
+        origin.x + ((x as f64 - logical_origin.x) * scale).round() as i32,
+        origin.y + ((y as f64 - logical_origin.y) * scale).round() as i32,
+    )
+}
+
+/// Parses a colour written like `#1e1e2e`.
+fn parse_color(text: &str) -> anyhow::Result<egui::Color32> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let value = (hex.len() == 6)
+        .then(|| u32::from_str_radix(hex, 16).ok())
+        .flatten()
+        .with_context(|| format!("{text:?} isn't a colour like #1e1e2e"))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok(egui::Color32::from_rgb(r, g, b))
+}
@@ -0,0 +1,197 @@
+use egui::{Key, Modifiers};
+
+use super::markdown::{self, Block};
+use crate::{
+    chat::{Message, Role},
+    ipc::WindowMessage,
+};
+
+/// A conversation with the model, and the message the user is writing.
+#[derive(Default)]
+pub struct Chat {
+    messages: Vec<Message>,
+    /// The replies parsed as Markdown, alongside the messages. The user's
+    /// messages are shown as they were written, so they're left empty.
+    rendered: Vec<Vec<Block>>,
+    draft: String,
+    /// The index of the message being edited, and what it's been changed to.
+    editing: Option<(usize, String)>,
+    /// Whether the host is writing a reply.
+    generating: bool,
+}
+impl Chat {
+    pub fn set_messages(&mut self, messages: Vec<Message>) {
+        self.rendered = messages.iter().map(render).collect();
+        self.messages = messages;
+    }
+
+    /// Adds an empty reply, which [`Chat::add_to_reply`] fills in.
+    pub fn start_reply(&mut self) {
+        self.generating = true;
+        self.messages.push(Message {
+            role: Role::Assistant,
+            text: String::new(),
+        });
+        self.rendered.push(vec![]);
+    }
+
+    pub fn add_to_reply(&mut self, text: &str) {
+        if let (Some(message), Some(rendered)) =
+            (self.messages.last_mut(), self.rendered.last_mut())
+        {
+            message.text.push_str(text);
+            *rendered = render(message);
+        }
+    }
+
+    pub fn finish_reply(&mut self) {
+        self.generating = false;
+    }
+
+    /// Shows the conversation, with the message being written below it.
+    ///
+    /// Returns the message to send to the host, if the user did something.
+    pub fn ui(&mut self, ui: &mut egui::Ui, status: Option<&str>) -> Option<WindowMessage> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            if let Some(status) = status {
+                ui.weak(status);
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("✕").on_hover_text("Close").clicked() {
+                    action = Some(WindowMessage::Cancel);
+                }
+                if ui
+                    .small_button("Export")
+                    .on_hover_text("Save the chat as Markdown")
+                    .clicked()
+                {
+                    action = Some(WindowMessage::Export);
+                }
+            });
+        });
+
+        let send = ui.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Enter));
+        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+            ui.horizontal(|ui| {
+                let can_send = !self.generating && self.editing.is_none();
+                let clicked = ui
+                    .add_enabled(can_send, egui::Button::new("Send"))
+                    .on_hover_text("Ctrl+Enter")
+                    .clicked();
+                if can_send && (clicked || send) && !self.draft.trim().is_empty() {
+                    action = Some(WindowMessage::Submit {
+                        text: std::mem::take(&mut self.draft),
+                    });
+                }
+                if self.generating {
+                    ui.spinner();
+                }
+            });
+            let draft_res = ui.add(
+                egui::TextEdit::multiline(&mut self.draft)
+                    .hint_text("Message")
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+            if self.editing.is_none() {
+                draft_res.request_focus();
+            }
+            ui.separator();
+
+            ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        if let Some(message) = self.messages_ui(ui, send) {
+                            action = Some(message);
+                        }
+                    });
+            });
+        });
+
+        if ui.input(|i| i.key_released(Key::Escape)) {
+            if self.editing.is_some() {
+                self.editing = None;
+            } else {
+                action = Some(WindowMessage::Cancel);
+            }
+        }
+        action
+    }
+
+    fn messages_ui(&mut self, ui: &mut egui::Ui, send: bool) -> Option<WindowMessage> {
+        let mut action = None;
+        let last = self.messages.len().saturating_sub(1);
+        for (index, (message, rendered)) in self.messages.iter().zip(&self.rendered).enumerate() {
+            match message.role {
+                Role::User => {
+                    ui.horizontal(|ui| {
+                        ui.strong("You");
+                        if !self.generating
+                            && self.editing.is_none()
+                            && ui.small_button("Edit").clicked()
+                        {
+                            self.editing = Some((index, message.text.clone()));
+                        }
+                    });
+                    match &mut self.editing {
+                        Some((editing, text)) if *editing == index => {
+                            ui.add(
+                                egui::TextEdit::multiline(text)
+                                    .desired_width(f32::INFINITY)
+                                    .desired_rows(1),
+                            )
+                            .request_focus();
+                            let (resend, cancel) = ui
+                                .horizontal(|ui| {
+                                    (
+                                        ui.small_button("Send")
+                                            .on_hover_text("Ctrl+Enter")
+                                            .clicked(),
+                                        ui.small_button("Cancel").clicked(),
+                                    )
+                                })
+                                .inner;
+                            if (resend || send) && !text.trim().is_empty() {
+                                action = Some(WindowMessage::Edit {
+                                    index,
+                                    text: std::mem::take(text),
+                                });
+                            }
+                            if resend || send || cancel {
+                                self.editing = None;
+                            }
+                        }
+                        _ => {
+                            ui.label(&message.text);
+                        }
+                    }
+                }
+                Role::Assistant => {
+                    ui.horizontal(|ui| {
+                        ui.strong("Assistant");
+                        if !self.generating {
+                            if ui.small_button("Copy").clicked() {
+                                ui.output_mut(|o| o.copied_text = message.text.clone());
+                            }
+                            if index == last && ui.small_button("Regenerate").clicked() {
+                                action = Some(WindowMessage::Regenerate);
+                            }
+                        }
+                    });
+                    markdown::show(ui, rendered);
+                }
+            }
+            ui.add_space(8.0);
+        }
+        action
+    }
+}
+
+fn render(message: &Message) -> Vec<Block> {
+    match message.role {
+        Role::User => vec![],
+        Role::Assistant => markdown::parse(&message.text),
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::Context;
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+
+use super::Painter;
+
+/// Draws the window with wgpu. This is the fastest option, but needs a GPU
+/// adapter, which headless machines and remote desktops may not have.
+pub struct GpuPainter {
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    surface_config: wgpu::SurfaceConfiguration,
+    render_pass: RenderPass,
+}
+impl GpuPainter {
+    pub async fn new(window: &winit::window::Window) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: Default::default(),
+        });
+        let surface = unsafe { instance.create_surface(window) }?;
+        let size = window.inner_size();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            })
+            .await
+            .context("no adapter")?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await?;
+        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.describe().srgb)
+            .unwrap_or(surface_caps.formats[0]);
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode: surface_caps.present_modes[0],
+            // See-through windows need the surface to be blended with what's behind it.
+            alpha_mode: if surface_caps
+                .alpha_modes
+                .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+            {
+                wgpu::CompositeAlphaMode::PreMultiplied
+            } else {
+                surface_caps.alpha_modes[0]
+            },
+            view_formats: vec![],
+        };
+        surface.configure(&device, &surface_config);
+
+        // We use the egui_wgpu_backend crate as the render backend.
+        let render_pass = RenderPass::new(&device, surface_format, 1);
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            surface_config,
+            render_pass,
+        })
+    }
+
+    fn draw(
+        &mut self,
+        scale_factor: f32,
+        paint_jobs: &[egui::ClippedPrimitive],
+    ) -> anyhow::Result<()> {
+        let output_frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated) => {
+                // This error occurs when the app is minimized on Windows.
+                // Silently return here to prevent spamming the console with:
+                // "The underlying surface has changed, and therefore the swap chain must be updated"
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let output_view = output_frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("encoder"),
+            });
+
+        let screen_descriptor = ScreenDescriptor {
+            physical_width: self.surface_config.width,
+            physical_height: self.surface_config.height,
+            scale_factor,
+        };
+        self.render_pass
+            .update_buffers(&self.device, &self.queue, paint_jobs, &screen_descriptor);
+
+        // Record all render passes.
+        self.render_pass
+            .execute(
+                &mut encoder,
+                &output_view,
+                paint_jobs,
+                &screen_descriptor,
+                Some(wgpu::Color::TRANSPARENT),
+            )
+            .unwrap();
+        // Submit the commands.
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        // Redraw egui
+        output_frame.present();
+        Ok(())
+    }
+}
+impl Painter for GpuPainter {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+
+    fn paint(
+        &mut self,
+        scale_factor: f32,
+        paint_jobs: &[egui::ClippedPrimitive],
+        textures_delta: egui::TexturesDelta,
+    ) -> anyhow::Result<()> {
+        // Upload all resources for the GPU. This happens even if the frame is
+        // dropped, so that later frames have the textures they need.
+        self.render_pass
+            .add_textures(&self.device, &self.queue, &textures_delta)
+            .expect("add texture ok");
+        let result = self.draw(scale_factor, paint_jobs);
+        self.render_pass
+            .remove_textures(textures_delta)
+            .expect("remove texture ok");
+        result
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+use egui::{text::LayoutJob, Color32, TextFormat};
+
+/// Words that are keywords in at least one common language. Prompts mix prose
+/// and code in no particular language, so some of these will be coloured in
+/// prose too; that beats having to guess the language.
+const KEYWORDS: &[&str] = &[
+    "async", "await", "break", "class", "const", "continue", "def", "elif", "else", "enum",
+    "export", "false", "False", "fn", "for", "function", "if", "impl", "import", "let", "match",
+    "mut", "None", "null", "pub", "return", "self", "static", "struct", "true", "True", "var",
+    "while", "yield",
+];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+/// Lays out `text` in a monospace font, colouring anything that looks like code.
+pub fn highlight(ui: &egui::Ui, text: &str) -> LayoutJob {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let dark_mode = ui.visuals().dark_mode;
+    let format = |kind| {
+        let color = match (kind, dark_mode) {
+            (Kind::Plain, _) => ui.visuals().text_color(),
+            (Kind::Keyword, true) => Color32::from_rgb(0xc6, 0x8a, 0xea),
+            (Kind::Keyword, false) => Color32::from_rgb(0x8a, 0x2b, 0xb8),
+            (Kind::String, true) => Color32::from_rgb(0x9a, 0xd0, 0x7c),
+            (Kind::String, false) => Color32::from_rgb(0x2f, 0x7d, 0x1f),
+            (Kind::Number, true) => Color32::from_rgb(0xe8, 0xa8, 0x62),
+            (Kind::Number, false) => Color32::from_rgb(0xa8, 0x5a, 0x00),
+            (Kind::Comment, _) => ui.visuals().weak_text_color(),
+        };
+        TextFormat::simple(font_id.clone(), color)
+    };
+
+    let mut job = LayoutJob::default();
+    for (kind, range) in tokenize(text) {
+        job.append(&text[range], 0.0, format(kind));
+    }
+    job
+}
+
+/// Splits `text` into runs of the same [`Kind`], which together cover all of `text`.
+fn tokenize(text: &str) -> Vec<(Kind, Range<usize>)> {
+    let mut tokens: Vec<(Kind, Range<usize>)> = vec![];
+    let mut start = 0;
+    let mut previous: Option<char> = None;
+    while let Some(c) = text[start..].chars().next() {
+        let rest = &text[start..];
+        let after_word = previous.is_some_and(|p| p.is_alphanumeric() || p == '_');
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+
+        let (kind, len) = if rest.starts_with("//") || (c == '#' && !after_word) {
+            (Kind::Comment, line_end)
+        } else if matches!(c, '"' | '`') || (c == '\'' && !after_word) {
+            // Only treat it as a string if it closes on the same line, so that
+            // a lone quote in prose doesn't colour everything after it.
+            match closing_quote(&rest[..line_end], c) {
+                Some(end) => (Kind::String, end),
+                None => (Kind::Plain, c.len_utf8()),
+            }
+        } else if c.is_ascii_digit() && !after_word {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            (Kind::Number, end)
+        } else if c.is_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let kind = if KEYWORDS.contains(&&rest[..end]) {
+                Kind::Keyword
+            } else {
+                Kind::Plain
+            };
+            (kind, end)
+        } else {
+            (Kind::Plain, c.len_utf8())
+        };
+
+        let end = start + len;
+        match tokens.last_mut() {
+            // Merge neighbouring plain text to keep the layout job small.
+            Some((Kind::Plain, last)) if kind == Kind::Plain => last.end = end,
+            _ => tokens.push((kind, start..end)),
+        }
+        previous = text[start..end].chars().next_back();
+        start = end;
+    }
+    tokens
+}
+
+/// Returns the length of the string starting with `quote` at the start of
+/// `line`, including both quotes, if it's closed.
+fn closing_quote(line: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in line.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(index + c.len_utf8()),
+            _ => {}
+        }
+    }
+    None
+}
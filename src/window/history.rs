@@ -0,0 +1,169 @@
+use egui::{Key, Modifiers};
+
+use crate::fuzzy;
+
+/// The prompts the user has entered before, and where they are in them.
+#[derive(Default)]
+pub struct History {
+    /// Oldest first.
+    entries: Vec<String>,
+    /// How far back from the newest entry the user has gone, if at all.
+    position: Option<usize>,
+    /// What the user had typed before they started going back through the history.
+    draft: String,
+    search: Option<Search>,
+}
+struct Search {
+    query: String,
+    /// Indices into the entries, best match first.
+    matches: Vec<usize>,
+    selected: usize,
+}
+impl History {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    /// Whether Up and Down should go through the history for `input`, rather
+    /// than move the cursor. This is the case when there's nothing typed, or
+    /// what's there came from the history untouched.
+    pub fn is_recalling(&self, input: &str) -> bool {
+        match self.position {
+            None => input.is_empty(),
+            Some(position) => self.entry(position) == input,
+        }
+    }
+
+    /// Handles Up and Down, replacing `input` with an older or newer entry.
+    ///
+    /// Returns `true` if `input` was changed.
+    pub fn recall(&mut self, ui: &mut egui::Ui, input: &mut String) -> bool {
+        let older = ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowUp));
+        let newer = ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowDown));
+
+        let position = match (older, newer, self.position) {
+            (true, _, None) if !self.entries.is_empty() => {
+                self.draft = input.clone();
+                0
+            }
+            (true, _, Some(position)) if position + 1 < self.entries.len() => position + 1,
+            (false, true, Some(0)) => {
+                self.position = None;
+                *input = std::mem::take(&mut self.draft);
+                return true;
+            }
+            (false, true, Some(position)) => position - 1,
+            _ => return false,
+        };
+        self.position = Some(position);
+        *input = self.entry(position).to_string();
+        true
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Starts a search when Ctrl+R is pressed.
+    pub fn start_search(&mut self, ui: &mut egui::Ui) {
+        if ui.input_mut(|i| i.consume_key(Modifiers::CTRL, Key::R)) {
+            let mut search = Search {
+                query: String::new(),
+                matches: vec![],
+                selected: 0,
+            };
+            search.update(&self.entries);
+            self.search = Some(search);
+        }
+    }
+
+    /// Shows the search bar. Enter puts the selected entry into `input`,
+    /// Escape leaves `input` alone, and Ctrl+R or Up and Down go through the
+    /// matches.
+    ///
+    /// Returns `true` if `input` was changed.
+    pub fn search_ui(&mut self, ui: &mut egui::Ui, input: &mut String) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+
+        let (next, previous) = ui.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::CTRL, Key::R)
+                    || i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+            )
+        });
+        if next && search.selected + 1 < search.matches.len() {
+            search.selected += 1;
+        }
+        if previous {
+            search.selected = search.selected.saturating_sub(1);
+        }
+
+        let selected = search.matches.get(search.selected).copied();
+        ui.horizontal(|ui| {
+            ui.label("search:");
+            let query = ui.add(egui::TextEdit::singleline(&mut search.query).desired_width(120.0));
+            query.request_focus();
+            if query.changed() {
+                search.update(&self.entries);
+            }
+            match selected {
+                Some(index) => {
+                    let entry = &self.entries[index];
+                    let first_line = entry.lines().next().unwrap_or_default();
+                    if first_line.len() < entry.len() {
+                        ui.weak(format!("{first_line}…"));
+                    } else {
+                        ui.weak(first_line);
+                    }
+                }
+                None => {
+                    ui.weak("no matches");
+                }
+            }
+        });
+
+        let (accept, dismiss) =
+            ui.input(|i| (i.key_released(Key::Enter), i.key_released(Key::Escape)));
+        if accept || dismiss {
+            self.search = None;
+        }
+        match selected {
+            Some(index) if accept => {
+                self.position = None;
+                *input = self.entries[index].clone();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn entry(&self, position: usize) -> &str {
+        &self.entries[self.entries.len() - 1 - position]
+    }
+}
+impl Search {
+    fn update(&mut self, entries: &[String]) {
+        // Newer entries go first when they match equally well.
+        self.matches = fuzzy::rank(&self.query, entries.iter().rev().map(String::as_str))
+            .into_iter()
+            .map(|index| entries.len() - 1 - index)
+            .collect();
+        self.selected = 0;
+    }
+}
+
+/// Moves the cursor of the text edit with `id` to the end of `text`, so that
+/// recalled text can be added to straight away.
+pub fn move_cursor_to_end(ui: &egui::Ui, id: egui::Id, text: &str) {
+    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), id) {
+        let end = egui::text::CCursor::new(text.chars().count());
+        state.set_ccursor_range(Some(egui::text::CCursorRange::one(end)));
+        state.store(ui.ctx(), id);
+    }
+}
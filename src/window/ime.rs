@@ -0,0 +1,101 @@
+use egui_winit_platform::Platform;
+use winit::{
+    event::{ElementState, Ime, VirtualKeyCode, WindowEvent},
+    window::Window,
+};
+
+/// Passes text being composed with an input method (IME) to egui, which
+/// `egui_winit_platform` doesn't do, and tells the IME where the text cursor
+/// is so that its candidate window appears next to it.
+#[derive(Default)]
+pub struct InputMethod {
+    /// Whether there's text being composed.
+    composing: bool,
+    /// Whether the release of the key that finished composing is still to come.
+    /// It was meant for the IME, so it mustn't submit or close the window.
+    swallow_release: bool,
+    /// Where the IME was last told the text cursor is, in points.
+    cursor: Option<egui::Pos2>,
+}
+impl InputMethod {
+    /// Handles `event` if it's to do with the IME.
+    ///
+    /// Returns `false` if the event was meant for the IME, and shouldn't be
+    /// passed on to the platform integration.
+    pub fn handle_event(&mut self, platform: &mut Platform, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Ime(ime) => {
+                let events = &mut platform.raw_input_mut().events;
+                match ime {
+                    // Windows enables and disables the IME around each
+                    // composition, but macOS doesn't, so composition is started
+                    // by the first preedit instead.
+                    Ime::Enabled | Ime::Disabled => {}
+                    Ime::Preedit(text, _) => {
+                        if !self.composing {
+                            if text.is_empty() {
+                                return true;
+                            }
+                            self.composing = true;
+                            events.push(egui::Event::CompositionStart);
+                        }
+                        events.push(egui::Event::CompositionUpdate(text.clone()));
+                        // An empty preedit means the composition was cancelled.
+                        if text.is_empty() {
+                            self.finish_composing();
+                            events.push(egui::Event::CompositionEnd(String::new()));
+                        }
+                    }
+                    Ime::Commit(text) => {
+                        self.finish_composing();
+                        events.push(egui::Event::CompositionEnd(text.clone()));
+                    }
+                }
+                true
+            }
+            WindowEvent::KeyboardInput { input, .. }
+                if matches!(
+                    input.virtual_keycode,
+                    Some(VirtualKeyCode::Return | VirtualKeyCode::Escape)
+                ) =>
+            {
+                match input.state {
+                    ElementState::Pressed => {
+                        self.swallow_release = false;
+                        !self.composing
+                    }
+                    ElementState::Released => {
+                        let meant_for_ime = self.composing || self.swallow_release;
+                        self.swallow_release = false;
+                        !meant_for_ime
+                    }
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Allows the IME only while a text field has focus, and moves its
+    /// candidate window to the text cursor.
+    pub fn update(&mut self, window: &Window, output: &egui::PlatformOutput) {
+        let cursor = output.text_cursor_pos;
+        if cursor == self.cursor {
+            return;
+        }
+        if cursor.is_some() != self.cursor.is_some() {
+            window.set_ime_allowed(cursor.is_some());
+        }
+        if let Some(cursor) = cursor {
+            // egui's points are winit's logical pixels, so this is right on HiDPI displays too.
+            window.set_ime_position(winit::dpi::LogicalPosition::new(cursor.x, cursor.y));
+        }
+        self.cursor = cursor;
+    }
+
+    fn finish_composing(&mut self) {
+        if self.composing {
+            self.composing = false;
+            self.swallow_release = true;
+        }
+    }
+}
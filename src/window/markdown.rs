@@ -0,0 +1,358 @@
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+
+use super::highlight;
+
+/// A piece of Markdown that's laid out on its own, like a paragraph or a list.
+pub enum Block {
+    Paragraph(Vec<Span>),
+    Heading(usize, Vec<Span>),
+    Code {
+        language: String,
+        code: String,
+    },
+    /// `start` is the number of the first item if the list is numbered.
+    List {
+        start: Option<u64>,
+        items: Vec<Vec<Block>>,
+    },
+    Quote(Vec<Block>),
+    /// Each row is a list of cells. The first row is the header.
+    Table(Vec<Vec<Vec<Span>>>),
+    Rule,
+}
+
+/// A run of text with the same formatting.
+pub struct Span {
+    text: String,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+/// Parses `text` as Markdown, with tables, strikethrough and task lists.
+pub fn parse(text: &str) -> Vec<Block> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    blocks(&mut Parser::new_ext(text, options))
+}
+
+/// Shows `blocks` one after the other.
+pub fn show(ui: &mut egui::Ui, blocks: &[Block]) {
+    for block in blocks {
+        show_block(ui, block);
+    }
+}
+
+fn show_block(ui: &mut egui::Ui, block: &Block) {
+    match block {
+        Block::Paragraph(spans) => show_spans(ui, spans, false, None),
+        Block::Heading(level, spans) => {
+            let heading = egui::TextStyle::Heading.resolve(ui.style()).size;
+            let body = egui::TextStyle::Body.resolve(ui.style()).size;
+            let size = match level {
+                1 => heading,
+                2 => (heading + body) / 2.0,
+                _ => body,
+            };
+            ui.add_space(4.0);
+            show_spans(ui, spans, true, Some(size));
+        }
+        Block::Code { language, code } => {
+            egui::Frame::group(ui.style())
+                .fill(ui.visuals().extreme_bg_color)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal(|ui| {
+                        ui.weak(language);
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("Copy").clicked() {
+                                ui.output_mut(|o| o.copied_text = code.clone());
+                            }
+                        });
+                    });
+                    let mut job = highlight::highlight(ui, code.trim_end_matches('\n'));
+                    job.wrap.max_width = ui.available_width();
+                    ui.label(job);
+                });
+        }
+        Block::List { start, items } => {
+            for (index, item) in items.iter().enumerate() {
+                let marker = match start {
+                    Some(start) => format!("{}.", start + index as u64),
+                    None => "•".to_string(),
+                };
+                ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+                    ui.label(marker);
+                    ui.vertical(|ui| show(ui, item));
+                });
+            }
+        }
+        Block::Quote(blocks) => {
+            let response = egui::Frame::none()
+                .inner_margin(egui::style::Margin {
+                    left: 10.0,
+                    ..Default::default()
+                })
+                .show(ui, |ui| show(ui, blocks))
+                .response;
+            ui.painter().vline(
+                response.rect.left() + 2.0,
+                response.rect.y_range(),
+                ui.visuals().widgets.noninteractive.bg_stroke,
+            );
+        }
+        Block::Table(rows) => {
+            egui::Grid::new(ui.next_auto_id())
+                .striped(true)
+                .show(ui, |ui| {
+                    for (index, row) in rows.iter().enumerate() {
+                        for cell in row {
+                            show_spans(ui, cell, index == 0, None);
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Block::Rule => {
+            ui.separator();
+        }
+    }
+}
+
+/// Shows `spans` as one block of wrapped text, made bold and resized if asked.
+fn show_spans(ui: &mut egui::Ui, spans: &[Span], strong: bool, size: Option<f32>) {
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for span in spans {
+            let mut text = egui::RichText::new(&span.text);
+            if span.code {
+                text = text.code();
+            }
+            if strong || span.strong {
+                text = text.strong();
+            }
+            if span.emphasis {
+                text = text.italics();
+            }
+            if span.strikethrough {
+                text = text.strikethrough();
+            }
+            if let Some(size) = size {
+                text = text.size(size);
+            }
+            match &span.link {
+                Some(url) => ui.hyperlink_to(text, url),
+                None => ui.label(text),
+            };
+        }
+    });
+}
+
+/// Reads blocks up to the end of the block they're in, or of the text.
+fn blocks<'a>(events: &mut impl Iterator<Item = Event<'a>>) -> Vec<Block> {
+    let mut blocks = vec![];
+    // Text that isn't in a paragraph, like the items of a tight list.
+    let mut loose = Spans::default();
+    while let Some(event) = events.next() {
+        let Some(event) = loose.push(event) else {
+            continue;
+        };
+        if !loose.spans.is_empty() {
+            blocks.push(Block::Paragraph(std::mem::take(&mut loose.spans)));
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) => blocks.push(Block::Paragraph(spans(events))),
+            Event::Start(Tag::Heading(level, ..)) => {
+                blocks.push(Block::Heading(level as usize, spans(events)))
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                let mut code = String::new();
+                for event in events.by_ref() {
+                    match event {
+                        Event::Text(text) => code.push_str(&text),
+                        _ => break,
+                    }
+                }
+                blocks.push(Block::Code { language, code });
+            }
+            Event::Start(Tag::List(start)) => {
+                let mut items = vec![];
+                while let Some(Event::Start(Tag::Item)) = events.next() {
+                    items.push(self::blocks(events));
+                }
+                blocks.push(Block::List { start, items });
+            }
+            Event::Start(Tag::BlockQuote) => blocks.push(Block::Quote(self::blocks(events))),
+            Event::Start(Tag::Table(_)) => {
+                let mut rows = vec![];
+                while let Some(Event::Start(Tag::TableHead | Tag::TableRow)) = events.next() {
+                    let mut cells = vec![];
+                    while let Some(Event::Start(Tag::TableCell)) = events.next() {
+                        cells.push(spans(events));
+                    }
+                    rows.push(cells);
+                }
+                blocks.push(Block::Table(rows));
+            }
+            // Anything else is shown as if it weren't there.
+            Event::Start(_) => blocks.extend(self::blocks(events)),
+            Event::Rule => blocks.push(Block::Rule),
+            Event::End(_) => break,
+            _ => {}
+        }
+    }
+    if !loose.spans.is_empty() {
+        blocks.push(Block::Paragraph(loose.spans));
+    }
+    blocks
+}
+
+/// Reads the text up to the end of the block it's in.
+fn spans<'a>(events: &mut impl Iterator<Item = Event<'a>>) -> Vec<Span> {
+    let mut spans = Spans::default();
+    for event in events.by_ref() {
+        if let Some(Event::End(_)) = spans.push(event) {
+            break;
+        }
+    }
+    spans.spans
+}
+
+/// Builds [`Span`]s from text and the formatting around it.
+#[derive(Default)]
+struct Spans {
+    spans: Vec<Span>,
+    /// How many of each kind of formatting the text is inside.
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    link: Option<String>,
+}
+impl Spans {
+    /// Adds an inline event, or hands it back if it isn't one.
+    fn push<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        match event {
+            Event::Start(Tag::Strong) => self.strong += 1,
+            Event::End(Tag::Strong) => self.strong -= 1,
+            Event::Start(Tag::Emphasis) => self.emphasis += 1,
+            Event::End(Tag::Emphasis) => self.emphasis -= 1,
+            Event::Start(Tag::Strikethrough) => self.strikethrough += 1,
+            Event::End(Tag::Strikethrough) => self.strikethrough -= 1,
+            // Images can't be shown, so their alt text links to them instead.
+            Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) => {
+                self.link = Some(url.to_string())
+            }
+            Event::End(Tag::Link(..) | Tag::Image(..)) => self.link = None,
+            Event::Text(text) | Event::Html(text) => self.add(&text, false),
+            Event::Code(code) => self.add(&code, true),
+            Event::SoftBreak => self.add(" ", false),
+            Event::HardBreak => self.add("\n", false),
+            Event::TaskListMarker(done) => self.add(if done { "☑ " } else { "☐ " }, false),
+            Event::FootnoteReference(name) => self.add(&format!("[{name}]"), false),
+            event => return Some(event),
+        }
+        None
+    }
+
+    fn add(&mut self, text: &str, code: bool) {
+        self.spans.push(Span {
+            text: text.to_string(),
+            strong: self.strong > 0,
+            emphasis: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+            code,
+            link: self.link.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(spans: &[Span]) -> String {
+        spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    /// The text of each item in `block`, which must be a list of paragraphs.
+    fn list_items(block: &Block) -> Vec<String> {
+        let Block::List { items, .. } = block else {
+            panic!("not a list");
+        };
+        items
+            .iter()
+            .map(|item| match &item[..] {
+                [Block::Paragraph(spans)] => text(spans),
+                _ => panic!("not a single paragraph"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tight_list_items_are_paragraphs() {
+        let blocks = parse("- one\n- **two**\n\nafter");
+        let [list, Block::Paragraph(after)] = &blocks[..] else {
+            panic!("expected a list and a paragraph");
+        };
+        assert!(matches!(list, Block::List { start: None, .. }));
+        assert_eq!(list_items(list), ["one", "two"]);
+        assert_eq!(text(after), "after");
+    }
+
+    #[test]
+    fn nested_list_stays_in_its_item() {
+        let blocks = parse("3. one\n   - inner\n   - more\n4. two");
+        let [Block::List {
+            start: Some(3),
+            items,
+        }] = &blocks[..]
+        else {
+            panic!("expected one numbered list");
+        };
+        let [first, second] = &items[..] else {
+            panic!("expected two items");
+        };
+        let [Block::Paragraph(one), inner] = &first[..] else {
+            panic!("expected text and a list in the first item");
+        };
+        assert_eq!(text(one), "one");
+        assert_eq!(list_items(inner), ["inner", "more"]);
+        assert!(matches!(&second[..], [Block::Paragraph(two)] if text(two) == "two"));
+    }
+
+    #[test]
+    fn table_header_is_the_first_row() {
+        let blocks = parse("| a | b |\n|---|---|\n| 1 | `2` |\n| 3 | 4 |\n\nafter");
+        let [Block::Table(rows), Block::Paragraph(after)] = &blocks[..] else {
+            panic!("expected a table and a paragraph");
+        };
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| text(cell)).collect())
+            .collect();
+        assert_eq!(rows, [["a", "b"], ["1", "2"], ["3", "4"]]);
+        assert_eq!(text(after), "after");
+    }
+
+    #[test]
+    fn fenced_code_keeps_its_language_and_text() {
+        let blocks = parse("```rust ignore\nfn main() {\n    *x = 1;\n}\n```\nafter");
+        let [Block::Code { language, code }, Block::Paragraph(after)] = &blocks[..] else {
+            panic!("expected a code block and a paragraph");
+        };
+        assert_eq!(language, "rust");
+        assert_eq!(code, "fn main() {\n    *x = 1;\n}\n");
+        assert_eq!(text(after), "after");
+    }
+}
@@ -0,0 +1,135 @@
+use egui::{Key, Modifiers};
+
+use super::PaletteEntry;
+use crate::fuzzy;
+
+/// What the user did with the palette this frame.
+pub enum Action {
+    /// The user picked the command at this index, and entered the text for it.
+    Pick(usize, String),
+    Cancel,
+}
+
+/// Lists commands to pick from, then asks for the prompt for the picked one.
+pub struct Palette {
+    entries: Vec<PaletteEntry>,
+    filter: String,
+    /// Indices into the entries that match the filter, best match first.
+    matches: Vec<usize>,
+    /// Which of the matches is highlighted.
+    selected: usize,
+    /// The command that's been picked, and the prompt being entered for it.
+    picked: Option<(usize, String)>,
+}
+impl Palette {
+    pub fn new(entries: Vec<PaletteEntry>) -> Self {
+        let mut palette = Self {
+            entries,
+            filter: String::new(),
+            matches: vec![],
+            selected: 0,
+            picked: None,
+        };
+        palette.update_matches();
+        palette
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Action> {
+        match &mut self.picked {
+            None => self.list_ui(ui),
+            Some((index, prompt)) => {
+                let entry = &self.entries[*index];
+                ui.heading(&entry.name);
+                if let Some(description) = &entry.description {
+                    ui.weak(description);
+                }
+                let prompt_res = ui.add(
+                    egui::TextEdit::singleline(prompt)
+                        .hint_text("Prompt")
+                        .desired_width(f32::INFINITY),
+                );
+                prompt_res.request_focus();
+
+                let (submit, back) =
+                    ui.input(|i| (i.key_released(Key::Enter), i.key_released(Key::Escape)));
+                if submit {
+                    let (index, prompt) = self.picked.take().unwrap();
+                    return Some(Action::Pick(index, prompt));
+                }
+                if back {
+                    // Go back to the list, rather than closing the window.
+                    self.picked = None;
+                }
+                None
+            }
+        }
+    }
+
+    fn list_ui(&mut self, ui: &mut egui::Ui) -> Option<Action> {
+        let (up, down) = ui.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+            )
+        });
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if down && self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+
+        let filter_res = ui.add(
+            egui::TextEdit::singleline(&mut self.filter)
+                .hint_text("Search commands")
+                .desired_width(f32::INFINITY),
+        );
+        filter_res.request_focus();
+        if filter_res.changed() {
+            self.update_matches();
+        }
+
+        let mut clicked = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (rank, &index) in self.matches.iter().enumerate() {
+                let entry = &self.entries[index];
+                let label = ui.selectable_label(rank == self.selected, &entry.name);
+                if rank == self.selected {
+                    label.scroll_to_me(None);
+                }
+                if label.clicked() {
+                    clicked = Some(index);
+                }
+                if let Some(description) = &entry.description {
+                    ui.weak(description);
+                }
+            }
+            if self.matches.is_empty() {
+                ui.weak("No matching commands");
+            }
+        });
+
+        let (enter, escape) =
+            ui.input(|i| (i.key_released(Key::Enter), i.key_released(Key::Escape)));
+        if escape {
+            return Some(Action::Cancel);
+        }
+        let picked = clicked.or_else(|| {
+            enter
+                .then(|| self.matches.get(self.selected).copied())
+                .flatten()
+        });
+        if let Some(index) = picked {
+            self.picked = Some((index, String::new()));
+        }
+        None
+    }
+
+    fn update_matches(&mut self) {
+        self.matches = fuzzy::rank(
+            &self.filter,
+            self.entries.iter().map(|entry| entry.name.as_str()),
+        );
+        self.selected = 0;
+    }
+}
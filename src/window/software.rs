@@ -0,0 +1,238 @@
+use std::{collections::HashMap, num::NonZeroU32};
+
+use anyhow::Context;
+use egui::{epaint::Primitive, Color32, Pos2, Rect};
+
+use super::Painter;
+
+/// Draws the window on the CPU and hands the pixels to the windowing system.
+/// This is slower than [`super::gpu::GpuPainter`], but works without a GPU.
+pub struct SoftwarePainter {
+    // The surface must not outlive the context it was created from.
+    surface: softbuffer::Surface,
+    _context: softbuffer::Context,
+    canvas: Canvas,
+    textures: HashMap<egui::TextureId, Texture>,
+}
+impl SoftwarePainter {
+    pub fn new(window: &winit::window::Window) -> anyhow::Result<Self> {
+        let context = unsafe { softbuffer::Context::new(window) }
+            .context("couldn't connect to the display for software rendering")?;
+        let surface = unsafe { softbuffer::Surface::new(&context, window) }
+            .context("couldn't create a surface for software rendering")?;
+
+        let mut painter = Self {
+            surface,
+            _context: context,
+            canvas: Canvas::default(),
+            textures: HashMap::new(),
+        };
+        let size = window.inner_size();
+        painter.resize(size.width, size.height);
+        Ok(painter)
+    }
+
+    fn set_texture(&mut self, id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
+        let ([width, height], pixels) = match &delta.image {
+            egui::ImageData::Color(image) => (image.size, image.pixels.clone()),
+            egui::ImageData::Font(image) => (image.size, image.srgba_pixels(None).collect()),
+        };
+
+        let Some([x, y]) = delta.pos else {
+            self.textures.insert(
+                id,
+                Texture {
+                    width,
+                    height,
+                    pixels,
+                },
+            );
+            return;
+        };
+        // Only part of the texture has changed.
+        let Some(texture) = self.textures.get_mut(&id) else {
+            return;
+        };
+        for (row, src) in pixels.chunks_exact(width).enumerate() {
+            let start = (y + row) * texture.width + x;
+            texture.pixels[start..start + width].copy_from_slice(src);
+        }
+    }
+}
+impl Painter for SoftwarePainter {
+    fn resize(&mut self, width: u32, height: u32) {
+        let (Some(nz_width), Some(nz_height)) = (NonZeroU32::new(width), NonZeroU32::new(height))
+        else {
+            return;
+        };
+        if let Err(err) = self.surface.resize(nz_width, nz_height) {
+            eprintln!("Couldn't resize the software surface: {err}");
+            return;
+        }
+        self.canvas = Canvas {
+            width: width as usize,
+            height: height as usize,
+            pixels: vec![Color32::TRANSPARENT; width as usize * height as usize],
+        };
+    }
+
+    fn paint(
+        &mut self,
+        scale_factor: f32,
+        paint_jobs: &[egui::ClippedPrimitive],
+        textures_delta: egui::TexturesDelta,
+    ) -> anyhow::Result<()> {
+        for (id, delta) in &textures_delta.set {
+            self.set_texture(*id, delta);
+        }
+
+        self.canvas.pixels.fill(Color32::TRANSPARENT);
+        for job in paint_jobs {
+            match &job.primitive {
+                Primitive::Mesh(mesh) => {
+                    if let Some(texture) = self.textures.get(&mesh.texture_id) {
+                        self.canvas
+                            .draw_mesh(texture, job.clip_rect, mesh, scale_factor);
+                    }
+                }
+                // We don't use any custom painting.
+                Primitive::Callback(_) => {}
+            }
+        }
+
+        let mut buffer = self.surface.buffer_mut()?;
+        for (out, pixel) in buffer.iter_mut().zip(&self.canvas.pixels) {
+            *out = u32::from_be_bytes([0, pixel.r(), pixel.g(), pixel.b()]);
+        }
+        buffer.present()?;
+
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+        Ok(())
+    }
+}
+
+/// The frame being drawn, with premultiplied alpha.
+#[derive(Default)]
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+impl Canvas {
+    fn draw_mesh(
+        &mut self,
+        texture: &Texture,
+        clip_rect: Rect,
+        mesh: &egui::Mesh,
+        scale_factor: f32,
+    ) {
+        // Everything is in points, but we draw in pixels.
+        let clip = Rect::from_min_max(
+            (clip_rect.min.to_vec2() * scale_factor).floor().to_pos2(),
+            (clip_rect.max.to_vec2() * scale_factor).ceil().to_pos2(),
+        )
+        .intersect(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(self.width as f32, self.height as f32),
+        ));
+        if clip.width() <= 0.0 || clip.height() <= 0.0 {
+            return;
+        }
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| {
+                let vertex = &mesh.vertices[index as usize];
+                ((vertex.pos.to_vec2() * scale_factor).to_pos2(), vertex)
+            });
+
+            let area = edge(a.0, b.0, c.0);
+            if area == 0.0 {
+                continue;
+            }
+
+            let bounds = Rect::from_points(&[a.0, b.0, c.0]).intersect(clip);
+            let (x0, x1) = (bounds.min.x.floor() as usize, bounds.max.x.ceil() as usize);
+            let (y0, y1) = (bounds.min.y.floor() as usize, bounds.max.y.ceil() as usize);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    // Barycentric weights; dividing by the area handles either winding.
+                    let weights = [
+                        edge(b.0, c.0, p) / area,
+                        edge(c.0, a.0, p) / area,
+                        edge(a.0, b.0, p) / area,
+                    ];
+                    if weights.iter().any(|w| *w < 0.0) {
+                        continue;
+                    }
+
+                    let vertices = [a.1, b.1, c.1];
+                    let uv = vertices
+                        .iter()
+                        .zip(weights)
+                        .fold(egui::Vec2::ZERO, |uv, (v, w)| uv + v.uv.to_vec2() * w);
+                    let color = interpolate(vertices.map(|v| v.color), weights);
+                    let src = multiply(texture.sample(uv.x, uv.y), color);
+
+                    let dst = &mut self.pixels[y * self.width + x];
+                    *dst = blend(src, *dst);
+                }
+            }
+        }
+    }
+}
+
+struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+impl Texture {
+    /// Returns the texel nearest to the normalised coordinates `u` and `v`.
+    fn sample(&self, u: f32, v: f32) -> Color32 {
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`.
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+fn interpolate(colors: [Color32; 3], weights: [f32; 3]) -> Color32 {
+    let channel = |i: usize| {
+        let value: f32 = colors
+            .iter()
+            .zip(weights)
+            .map(|(color, weight)| color.to_array()[i] as f32 * weight)
+            .sum();
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Color32::from_rgba_premultiplied(channel(0), channel(1), channel(2), channel(3))
+}
+
+fn multiply(a: Color32, b: Color32) -> Color32 {
+    let channel = |x: u8, y: u8| ((x as u32 * y as u32 + 127) / 255) as u8;
+    Color32::from_rgba_premultiplied(
+        channel(a.r(), b.r()),
+        channel(a.g(), b.g()),
+        channel(a.b(), b.b()),
+        channel(a.a(), b.a()),
+    )
+}
+
+/// Draws the premultiplied `src` over `dst`.
+fn blend(src: Color32, dst: Color32) -> Color32 {
+    let inverse = 255 - src.a() as u32;
+    let channel = |s: u8, d: u8| (s as u32 + (d as u32 * inverse + 127) / 255).min(255) as u8;
+    Color32::from_rgba_premultiplied(
+        channel(src.r(), dst.r()),
+        channel(src.g(), dst.g()),
+        channel(src.b(), dst.b()),
+        channel(src.a(), dst.a()),
+    )
+}